@@ -0,0 +1,237 @@
+use std::sync::OnceLock;
+
+use crate::bit_functions::{bishop_ray_mask, count_bits, king_step_mask, knight_shift_mask, rook_ray_mask};
+use crate::constants::*;
+
+// Precomputed attack tables, built once on first use from the ray/shift based mask functions
+// Knight and king attacks are a plain lookup by square; sliding pieces use "fancy" magic
+// bitboards, where the relevant blockers are hashed by a magic multiply into a per-square
+// slice of one shared attack table
+
+static ATTACK_TABLES: OnceLock<AttackTables> = OnceLock::new();
+
+pub fn attack_tables() -> &'static AttackTables {
+    ATTACK_TABLES.get_or_init(AttackTables::new)
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Magic {
+    pub mask:   u64,   // Squares whose occupancy can block the slider (board edges excluded)
+    pub magic:  u64,
+    pub shift:  u32,
+    pub offset: usize  // Start of this square's slice of the shared attack table
+}
+
+impl Magic {
+    fn index(&self, occupancy: u64) -> usize {
+        self.offset + ((occupancy & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+pub struct AttackTables {
+    pub knight:        [u64; 64],
+    pub king:          [u64; 64],
+    pub bishop_magics: [Magic; 64],
+    pub rook_magics:   [Magic; 64],
+    bishop_table:      Vec<u64>,
+    rook_table:        Vec<u64>
+}
+
+impl AttackTables {
+    fn new() -> AttackTables {
+
+        let mut knight: [u64; 64] = [0; 64];
+        let mut king: [u64; 64] = [0; 64];
+
+        for (sq, (n, k)) in knight.iter_mut().zip(king.iter_mut()).enumerate() {
+            let bit: u64 = 1 << sq;
+            *n = knight_shift_mask(bit, bit);
+            *k = king_step_mask(bit, bit);
+        }
+
+        let (bishop_magics, bishop_table) = build_magics(bishop_relevant_mask, bishop_ray_mask);
+        let (rook_magics, rook_table) = build_magics(rook_relevant_mask, rook_ray_mask);
+
+        AttackTables {
+            knight,
+            king,
+            bishop_magics,
+            rook_magics,
+            bishop_table,
+            rook_table
+        }
+    }
+
+    pub fn bishop_attacks(&self, sq: usize, occupancy: u64) -> u64 {
+        // Attacked squares up to and including the first blocker in each direction
+        self.bishop_table[self.bishop_magics[sq].index(occupancy)]
+    }
+
+    pub fn rook_attacks(&self, sq: usize, occupancy: u64) -> u64 {
+        self.rook_table[self.rook_magics[sq].index(occupancy)]
+    }
+}
+
+fn bishop_relevant_mask(bit: u64) -> u64 {
+    // A piece on the last square of a ray can't block anything behind it, so edges are dropped
+    bishop_ray_mask(bit, 0, 0) & !(RANK_1 | RANK_8 | FILE_A | FILE_H)
+}
+
+fn rook_relevant_mask(bit: u64) -> u64 {
+
+    let (rank_ends, file_ends): (u64, u64) = (FILE_A | FILE_H, RANK_1 | RANK_8);
+    let rank: u64 = RANK_1 << (bit.trailing_zeros() / 8 * 8);
+    let file: u64 = FILE_A << (bit.trailing_zeros() % 8);
+
+    ((rank & !rank_ends) | (file & !file_ends)) & !bit
+}
+
+// xorshift64* - deterministic so that the same magics are found on every run
+struct MagicRng {
+    state: u64
+}
+
+impl MagicRng {
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(2685821657736338717)
+    }
+
+    fn sparse(&mut self) -> u64 {
+        // Magics with few set bits are found far more quickly
+        self.next() & self.next() & self.next()
+    }
+}
+
+fn build_magics(relevant_mask: fn(u64) -> u64, ray_mask: fn(u64, u64, u64) -> u64) -> ([Magic; 64], Vec<u64>) {
+
+    // Seeds (by rank) known to find magics quickly with this generator
+    const SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+    let mut magics: [Magic; 64] = [Magic::default(); 64];
+    let mut table: Vec<u64> = vec![];
+
+    let mut occupancies: Vec<u64> = Vec::with_capacity(4096);
+    let mut attacks: Vec<u64> = Vec::with_capacity(4096);
+    // Each slot records the attempt that last wrote it, which avoids clearing between attempts
+    let mut used: Vec<usize> = vec![0; 4096];
+
+    for sq in 0..64 {
+
+        let bit: u64 = 1 << sq;
+        let mask: u64 = relevant_mask(bit);
+        let bits: u32 = count_bits(mask) as u32;
+        let size: usize = 1 << bits;
+
+        // Enumerate every subset of the mask (Carry-Rippler) along with its attack set
+        occupancies.clear();
+        attacks.clear();
+        let mut subset: u64 = 0;
+        loop {
+            occupancies.push(subset);
+            attacks.push(ray_mask(bit, 0, subset));
+            subset = subset.wrapping_sub(mask) & mask;
+            if subset == 0 {
+                break;
+            }
+        }
+
+        let mut magic: Magic = Magic {
+            mask,
+            magic: 0,
+            shift: 64 - bits,
+            offset: table.len()
+        };
+        table.resize(table.len() + size, 0);
+
+        let mut rng: MagicRng = MagicRng { state: SEEDS[sq / 8] };
+        let mut attempt: usize = 0;
+
+        'search: loop {
+            magic.magic = rng.sparse();
+
+            // Quickly reject candidates that don't spread the mask into the top bits
+            if count_bits(mask.wrapping_mul(magic.magic) & 0xFF00000000000000) < 6 {
+                continue;
+            }
+
+            attempt += 1;
+
+            for (occupancy, attack) in occupancies.iter().zip(attacks.iter()) {
+                let index: usize = magic.index(*occupancy);
+                let slot: usize = index - magic.offset;
+
+                if used[slot] < attempt {
+                    used[slot] = attempt;
+                    table[index] = *attack;
+                } else if table[index] != *attack {
+                    // Destructive collision - try another magic
+                    continue 'search;
+                }
+            }
+
+            break;
+        }
+
+        used.iter_mut().for_each(|u| *u = 0);
+        magics[sq] = magic;
+    }
+
+    (magics, table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_functions::{bishop_move_mask, king_move_mask, knight_move_mask, rook_move_mask};
+
+    #[test]
+    fn sliding_attacks_match_ray_masks() {
+        let tables: &AttackTables = attack_tables();
+        let mut rng: MagicRng = MagicRng { state: 0x9E3779B97F4A7C15 };
+
+        for sq in 0..64 {
+            let bit: u64 = 1 << sq;
+
+            // Sparse and dense boards, along with an empty one
+            for i in 0..2000 {
+                let occupancy: u64 = match i {
+                    0 => 0,
+                    _ if i % 2 == 0 => rng.sparse(),
+                    _ => rng.next()
+                } & !bit;
+
+                assert_eq!(tables.bishop_attacks(sq, occupancy), bishop_ray_mask(bit, 0, occupancy), "bishop on {} with {:#x}", sq, occupancy);
+                assert_eq!(tables.rook_attacks(sq, occupancy), rook_ray_mask(bit, 0, occupancy), "rook on {} with {:#x}", sq, occupancy);
+
+                // And through the move masks, which also split the blockers by side
+                let own: u64 = occupancy & rng.next();
+                let enemy: u64 = occupancy & !own;
+                assert_eq!(bishop_move_mask(bit, own, enemy), bishop_ray_mask(bit, own, enemy), "bishop moves on {}", sq);
+                assert_eq!(rook_move_mask(bit, own, enemy), rook_ray_mask(bit, own, enemy), "rook moves on {}", sq);
+            }
+        }
+    }
+
+    #[test]
+    fn step_attacks_match_shift_masks() {
+        let tables: &AttackTables = attack_tables();
+        let mut rng: MagicRng = MagicRng { state: 0x2545F4914F6CDD1D };
+
+        for sq in 0..64 {
+            let bit: u64 = 1 << sq;
+            assert_eq!(tables.knight[sq], knight_shift_mask(bit, bit), "knight on {}", sq);
+            assert_eq!(tables.king[sq], king_step_mask(bit, bit), "king on {}", sq);
+
+            let own: u64 = rng.next() & !bit;
+            assert_eq!(knight_move_mask(bit, own), knight_shift_mask(bit, own | bit), "knight moves on {}", sq);
+            assert_eq!(king_move_mask(bit, own), king_step_mask(bit, own | bit), "king moves on {}", sq);
+        }
+
+        // Totals over the board, which don't depend on the generators being right
+        assert_eq!(tables.knight.iter().map(|a| count_bits(*a) as u32).sum::<u32>(), 336);
+        assert_eq!(tables.king.iter().map(|a| count_bits(*a) as u32).sum::<u32>(), 420);
+    }
+}
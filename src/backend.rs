@@ -0,0 +1,218 @@
+use std::error::Error;
+use std::io::{self, Stdout, Write};
+
+use crossterm::{
+    ExecutableCommand, QueueableCommand,
+    terminal, cursor, style::{self, Attribute, Color, Colors}
+};
+
+// Drawing surfaces for the Renderer
+//
+// The Renderer works out what goes where on screen, and a backend puts it there - either the
+// terminal, through crossterm, or a grid of characters in memory, which other front ends can
+// read the board from and which can be compared against expected output
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Style {
+    pub foreground: Color,
+    pub background: Color,
+    pub reverse:    bool  // Reverse video
+}
+
+impl Style {
+    pub const PLAIN: Style = Style {
+        foreground: Color::Reset,
+        background: Color::Reset,
+        reverse:    false
+    };
+}
+
+pub trait Backend {
+
+    // Columns and rows there are to draw in
+    fn size(&self) -> (u16, u16);
+
+    fn clear_all(&mut self) -> Result<(), Box<dyn Error>>;
+
+    // Text is drawn along a single row from the given cell, one cell to a character
+    fn print(&mut self, column: u16, row: u16, text: &str, style: Style) -> Result<(), Box<dyn Error>>;
+
+    // Blanks the rest of a row from the given cell
+    fn clear_line(&mut self, column: u16, row: u16) -> Result<(), Box<dyn Error>>;
+
+    // The cursor is where typing carries on once drawing is done, and is saved and restored
+    // around anything drawn while the player might be typing
+    fn set_cursor(&mut self, column: u16, row: u16) -> Result<(), Box<dyn Error>>;
+    fn save_cursor(&mut self) -> Result<(), Box<dyn Error>>;
+    fn restore_cursor(&mut self) -> Result<(), Box<dyn Error>>;
+
+    // Anything drawn may be held back until this is called
+    fn flush(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct TerminalBackend {
+    stdout: Stdout
+}
+
+impl TerminalBackend {
+    pub fn new() -> TerminalBackend {
+        TerminalBackend {
+            stdout: io::stdout()
+        }
+    }
+}
+
+impl Default for TerminalBackend {
+    fn default() -> TerminalBackend {
+        TerminalBackend::new()
+    }
+}
+
+impl Backend for TerminalBackend {
+
+    fn size(&self) -> (u16, u16) {
+        terminal::size().unwrap_or((80, 24))
+    }
+
+    fn clear_all(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+        Ok(())
+    }
+
+    fn print(&mut self, column: u16, row: u16, text: &str, style: Style) -> Result<(), Box<dyn Error>> {
+        self.stdout
+            .queue(cursor::MoveTo(column, row))?
+            .queue(style::SetColors(Colors::new(style.foreground, style.background)))?
+            .queue(style::SetAttribute(if style.reverse {Attribute::Reverse} else {Attribute::NoReverse}))?
+            .queue(style::Print(text))?;
+        Ok(())
+    }
+
+    fn clear_line(&mut self, column: u16, row: u16) -> Result<(), Box<dyn Error>> {
+        // Terminals clear with the current background colour, so it is reset first
+        self.stdout
+            .queue(cursor::MoveTo(column, row))?
+            .queue(style::SetColors(Colors::new(Color::Reset, Color::Reset)))?
+            .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, column: u16, row: u16) -> Result<(), Box<dyn Error>> {
+        self.stdout.queue(cursor::MoveTo(column, row))?;
+        Ok(())
+    }
+
+    fn save_cursor(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stdout.queue(cursor::SavePosition)?;
+        Ok(())
+    }
+
+    fn restore_cursor(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stdout.queue(cursor::RestorePosition)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        // Whatever is printed next, eg. the status line, shouldn't pick up the board's colours
+        self.stdout
+            .queue(style::SetColors(Colors::new(Color::Reset, Color::Reset)))?
+            .queue(style::SetAttribute(Attribute::NoReverse))?
+            .flush()?;
+        Ok(())
+    }
+}
+
+pub struct TextBackend {
+    columns: u16,
+    rows:    u16,
+    cells:   Vec<(char, Style)>, // Row by row
+    cursor:  (u16, u16),
+    saved:   (u16, u16)
+}
+
+impl TextBackend {
+    pub fn new(columns: u16, rows: u16) -> TextBackend {
+        TextBackend {
+            columns,
+            rows,
+            cells:  vec![(' ', Style::PLAIN); columns as usize * rows as usize],
+            cursor: (0, 0),
+            saved:  (0, 0)
+        }
+    }
+
+    pub fn text(&self) -> String {
+        // The grid's characters, a line to a row, without trailing spaces
+        self.cells.chunks(self.columns.max(1) as usize)
+            .map(|row| row.iter().map(|(c, _)| *c).collect::<String>().trim_end().to_string())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn style_at(&self, column: u16, row: u16) -> Option<Style> {
+        self.index(column, row).map(|index| self.cells[index].1)
+    }
+
+    pub fn cursor(&self) -> (u16, u16) {
+        self.cursor
+    }
+
+    fn index(&self, column: u16, row: u16) -> Option<usize> {
+        if column < self.columns && row < self.rows {
+            Some(row as usize * self.columns as usize + column as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl Backend for TextBackend {
+
+    fn size(&self) -> (u16, u16) {
+        (self.columns, self.rows)
+    }
+
+    fn clear_all(&mut self) -> Result<(), Box<dyn Error>> {
+        self.cells.fill((' ', Style::PLAIN));
+        Ok(())
+    }
+
+    fn print(&mut self, column: u16, row: u16, text: &str, style: Style) -> Result<(), Box<dyn Error>> {
+        // Anything off the edge of the grid is lost, as it would be in a terminal
+        for (i, c) in text.chars().enumerate() {
+            if let Some(index) = self.index(column + i as u16, row) {
+                self.cells[index] = (c, style);
+            }
+        }
+        self.cursor = (column + text.chars().count() as u16, row);
+        Ok(())
+    }
+
+    fn clear_line(&mut self, column: u16, row: u16) -> Result<(), Box<dyn Error>> {
+        for column in column..self.columns {
+            if let Some(index) = self.index(column, row) {
+                self.cells[index] = (' ', Style::PLAIN);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, column: u16, row: u16) -> Result<(), Box<dyn Error>> {
+        self.cursor = (column, row);
+        Ok(())
+    }
+
+    fn save_cursor(&mut self) -> Result<(), Box<dyn Error>> {
+        self.saved = self.cursor;
+        Ok(())
+    }
+
+    fn restore_cursor(&mut self) -> Result<(), Box<dyn Error>> {
+        self.cursor = self.saved;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::env;
+use std::error::Error;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use crabablanca::board::Board;
+use crabablanca::engine::{format_eval, search, SearchOptions, SearchResult, TranspositionTable, MAX_DEPTH};
+use crabablanca::evaluation::EvalParams;
+
+// Search benchmark
+//
+// Searches a fixed set of positions for a fixed time each and reports the depth reached and
+// the nodes searched, to compare thread counts or changes to the search. Each position starts
+// with an empty transposition table so that results don't depend on the order they're run in
+//
+// With --nodes, each thread instead searches up to a fixed number of nodes, which stands in for
+// giving every thread a core of its own - threads sharing cores take time from each other, so
+// timed runs only show what threads gain with as many cores as threads
+
+const USAGE: &str = "Usage: bench [--threads <n>] [--movetime <ms> | --nodes <n>] [--hash <mb>]";
+
+const POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r1bqk1nr/ppp2ppp/2np4/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 w kq - 0 5",
+    "r2q1rk1/pp2bppp/2n1pn2/3p4/3P4/2NBPN2/PP3PPP/R2Q1RK1 w - - 0 10",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"
+];
+
+fn main() -> Result<(), Box<dyn Error>> {
+
+    let mut threads: usize = 1;
+    let mut movetime: u64 = 3000;
+    let mut nodes: Option<u64> = None;
+    let mut hash_mb: usize = 64;
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threads" => threads = args.next().ok_or(USAGE)?.parse()?,
+            "--movetime" => movetime = args.next().ok_or(USAGE)?.parse()?,
+            "--nodes" => nodes = Some(args.next().ok_or(USAGE)?.parse()?),
+            "--hash" => hash_mb = args.next().ok_or(USAGE)?.parse()?,
+            _ => return Err(USAGE.into())
+        }
+    }
+
+    let params: EvalParams = EvalParams::default();
+    let tt: TranspositionTable = TranspositionTable::new(hash_mb);
+    let options: SearchOptions = SearchOptions {
+        depth:    MAX_DEPTH,
+        time:     if nodes.is_some() {None} else {Some(Duration::from_millis(movetime))},
+        nodes,
+        threads,
+        multi_pv: 1
+    };
+
+    let mut total_depth: usize = 0;
+    let mut total_nodes: u64 = 0;
+    let mut total_seconds: f64 = 0.0;
+
+    for fen in POSITIONS {
+        let board: Board = Board::from_fen(fen).ok_or("Invalid benchmark position")?;
+
+        tt.clear();
+        let stop: AtomicBool = AtomicBool::new(false);
+        let result: SearchResult = search(&board, &params, None, &tt, &options, &stop, &mut |_| {});
+
+        println!("depth {:>2}  nodes {:>10}  eval {:>7}  {}", result.depth, result.nodes, format_eval(result.eval), fen);
+
+        total_depth += result.depth;
+        total_nodes += result.nodes;
+        total_seconds += result.elapsed.as_secs_f64();
+    }
+
+    println!("{} threads: average depth {:.1}, {} nodes, {:.0} nodes/s",
+             threads,
+             total_depth as f64 / POSITIONS.len() as f64,
+             total_nodes,
+             total_nodes as f64 / total_seconds);
+
+    Ok(())
+}
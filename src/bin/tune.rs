@@ -0,0 +1,189 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+
+use crabablanca::board::Board;
+use crabablanca::evaluation::{evaluate, EvalParams};
+
+// Texel-style tuning of the evaluation parameters
+//
+// Reads a file of positions labelled with the result of the game they were taken from, and
+// adjusts EvalParams to minimise the mean squared error between the results and the static
+// evaluations mapped through a sigmoid. Each line holds a FEN followed by the result, as
+// either 1-0, 0-1 or 1/2-1/2 (quoted or not), or [1.0], [0.5] or [0.0]:
+//
+//     rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 [0.5]
+//     r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - c9 "1-0";
+//
+// Positions are best taken from quiet points in games, since there is no search to resolve
+// captures that are in progress
+
+const USAGE: &str = "Usage: tune <positions> [--params <file>] [--out <file>] [--passes <n>]";
+
+struct LabelledPosition {
+    board:  Board,
+    result: f64 // From white's point of view - 1.0 for a win, 0.5 for a draw, 0.0 for a loss
+}
+
+fn parse_result(line: &str) -> Option<f64> {
+
+    // The draw is checked first since "1/2-1/2" contains "2-1"
+    if line.contains("1/2-1/2") || line.contains("[0.5]") {
+        Some(0.5)
+    } else if line.contains("1-0") || line.contains("[1.0]") {
+        Some(1.0)
+    } else if line.contains("0-1") || line.contains("[0.0]") {
+        Some(0.0)
+    } else {
+        None
+    }
+}
+
+fn load_positions(path: &str, params: &EvalParams) -> Result<Vec<LabelledPosition>, Box<dyn Error>> {
+
+    let mut positions: Vec<LabelledPosition> = vec![];
+    let mut skipped: usize = 0;
+
+    for line in fs::read_to_string(path)?.lines() {
+
+        // Only the first four FEN fields matter, and anything after them may be EPD opcodes
+        let fen: String = line.split_whitespace().take(4).collect::<Vec<&str>>().join(" ");
+
+        match (Board::from_fen(&fen), parse_result(line)) {
+            (Some(board), Some(result)) => {
+                // Checkmates are scored outside of the parameters, so can't inform tuning
+                if evaluate(&board, params).abs() < 9999.0 {
+                    positions.push(LabelledPosition { board, result });
+                } else {
+                    skipped += 1;
+                }
+            },
+            _ => skipped += 1
+        }
+    }
+
+    println!("Loaded {} positions ({} skipped)", positions.len(), skipped);
+
+    Ok(positions)
+}
+
+fn sigmoid(score: f64, k: f64) -> f64 {
+    // Scores are in pawns, so this is the usual 1 / (1 + 10^(-k * centipawns / 400))
+    1.0 / (1.0 + 10f64.powf(-k * score / 4.0))
+}
+
+fn mean_error(positions: &[LabelledPosition], params: &EvalParams, k: f64) -> f64 {
+
+    let total: f64 = positions.iter().map(|p| {
+        let error: f64 = p.result - sigmoid(evaluate(&p.board, params), k);
+        error * error
+    }).sum();
+
+    total / positions.len() as f64
+}
+
+fn fit_k(positions: &[LabelledPosition], params: &EvalParams) -> f64 {
+
+    // Scaling constant that best fits the starting parameters - kept fixed while tuning so
+    // that the parameters can't just shrink or grow the evaluation as a whole
+    let mut best_k: f64 = 1.0;
+    let mut best_error: f64 = mean_error(positions, params, best_k);
+
+    for step in [0.1, 0.01] {
+        let centre: f64 = best_k;
+        for i in -10..=10 {
+            let k: f64 = centre + step * i as f64;
+            if k <= 0.0 {
+                continue;
+            }
+            let error: f64 = mean_error(positions, params, k);
+            if error < best_error {
+                best_error = error;
+                best_k = k;
+            }
+        }
+    }
+
+    best_k
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+
+    let mut positions_path: Option<String> = None;
+    let mut params: EvalParams = EvalParams::default();
+    let mut out_path: String = String::from("tuned.toml");
+    let mut max_passes: usize = 100;
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--params" => params = EvalParams::load(&args.next().ok_or(USAGE)?)?,
+            "--out" => out_path = args.next().ok_or(USAGE)?,
+            "--passes" => max_passes = args.next().ok_or(USAGE)?.parse()?,
+            _ if positions_path.is_none() && !arg.starts_with("--") => positions_path = Some(arg),
+            _ => return Err(USAGE.into())
+        }
+    }
+
+    let positions: Vec<LabelledPosition> = load_positions(&positions_path.ok_or(USAGE)?, &params)?;
+
+    if positions.is_empty() {
+        return Err("No usable positions to tune on".into());
+    }
+
+    let k: f64 = fit_k(&positions, &params);
+    let mut best_error: f64 = mean_error(&positions, &params, k);
+    println!("K = {:.2}, starting error {:.6}", k, best_error);
+
+    // Pawn value is left alone, so that scores stay measured in pawns
+    let names: Vec<&'static str> = params.fields().into_iter().map(|(name, _)| name).filter(|name| *name != "pawn_value").collect();
+
+    // Local search: nudge each parameter up or down, keeping any change that reduces the
+    // error, and halve a parameter's step once neither direction helps
+    let mut steps: Vec<f64> = params.fields().into_iter()
+        .filter(|(name, _)| *name != "pawn_value")
+        .map(|(_, value)| (0.1 * value.abs()).max(0.01))
+        .collect();
+
+    for pass in 1..=max_passes {
+
+        let mut improved: bool = false;
+
+        for (name, step) in names.iter().zip(steps.iter_mut()) {
+
+            let value: f64 = params.fields().into_iter().find(|(field, _)| field == name).map(|(_, v)| v).unwrap_or(0.0);
+            let mut moved: bool = false;
+
+            for candidate in [value + *step, value - *step] {
+                let mut trial: EvalParams = params;
+                trial.set(name, candidate)?;
+
+                let error: f64 = mean_error(&positions, &trial, k);
+                if error < best_error {
+                    best_error = error;
+                    params = trial;
+                    moved = true;
+                    break;
+                }
+            }
+
+            if moved {
+                improved = true;
+            } else {
+                *step /= 2.0;
+            }
+        }
+
+        println!("Pass {}: error {:.6}", pass, best_error);
+        params.save(&out_path)?;
+
+        if !improved && steps.iter().all(|step| *step < 1e-4) {
+            break;
+        }
+    }
+
+    println!("Tuned parameters written to {}", out_path);
+
+    Ok(())
+}
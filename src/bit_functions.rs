@@ -1,3 +1,4 @@
+use crate::attack_tables::attack_tables;
 use crate::constants::*;
 
 pub fn get_lsb(bits: u64) -> u64 {
@@ -115,6 +116,12 @@ pub fn get_bit_rf(bit: u64) -> (u8, u8) {
     ((bit.trailing_zeros() / 8) as u8, (bit.trailing_zeros() % 8) as u8)
 }
 
+pub fn square_name(bit: u64) -> String {
+    // Algebraic name of a single square, eg. "e4"
+    let (rank, file) = get_bit_rf(bit);
+    format!("{}{}", (b'a' + file) as char, rank + 1)
+}
+
 pub fn pawn_capture_mask(bit: u64, to_move: u8) -> u64 {
 
     if to_move == 1 { // White pawn capture mask
@@ -152,7 +159,9 @@ pub fn sd(bit: u64) -> u64 {
     if bit & RANK_1 != 0 {0} else {bit >> 8}
 }
 
-pub fn knight_move_mask(bit: u64, own_pieces: u64) -> u64 {
+pub fn knight_shift_mask(bit: u64, own_pieces: u64) -> u64 {
+
+    // Computed from scratch - only used to build the knight lookup table
 
     let mut mask: u64 = 0;
     let mut shifts: Vec<i8> = vec![15, 17, 10, -6, -15, -17, -10, 6];
@@ -188,8 +197,8 @@ pub fn knight_move_mask(bit: u64, own_pieces: u64) -> u64 {
         shifts[5] = 0;
     }
     if (bit & RANK_7) != 0 {
+        shifts[0] = 0;
         shifts[1] = 0;
-        shifts[2] = 0;
     }
     if (bit & RANK_8) != 0 {
         shifts[0] = 0;
@@ -208,8 +217,8 @@ pub fn knight_move_mask(bit: u64, own_pieces: u64) -> u64 {
     mask & !own_pieces & !bit
 }
 
-pub fn bishop_move_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
-    // TODO: tidy this up a bit (make more bitwise)
+pub fn bishop_ray_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
+    // Computed from scratch - only used to build the bishop magic tables
     let (r, f) = get_bit_rf(bit);
 
     let main_diag: u64;
@@ -235,7 +244,8 @@ pub fn bishop_move_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
     // (main_diag | anti_diag) & !bit
 }
 
-pub fn rook_move_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
+pub fn rook_ray_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
+    // Computed from scratch - only used to build the rook magic tables
 
     let (r, f) = get_bit_rf(bit);
 
@@ -247,6 +257,42 @@ pub fn rook_move_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
     // ((RANK_1 << 8 * r) | (FILE_A << f)) & !bit
 }
 
+pub fn knight_move_mask(bit: u64, own_pieces: u64) -> u64 {
+
+    if bit == 0 {
+        return 0
+    }
+
+    attack_tables().knight[bit.trailing_zeros() as usize] & !own_pieces
+}
+
+pub fn bishop_move_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
+
+    if bit == 0 {
+        return 0
+    }
+
+    attack_tables().bishop_attacks(bit.trailing_zeros() as usize, (own_pieces | enemy_pieces) & !bit) & !own_pieces
+}
+
+pub fn rook_move_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
+
+    if bit == 0 {
+        return 0
+    }
+
+    attack_tables().rook_attacks(bit.trailing_zeros() as usize, (own_pieces | enemy_pieces) & !bit) & !own_pieces
+}
+
+pub fn king_move_mask(bit: u64, own_pieces: u64) -> u64 {
+
+    if bit == 0 {
+        return 0
+    }
+
+    attack_tables().king[bit.trailing_zeros() as usize] & !own_pieces
+}
+
 pub fn queen_move_mask(bit: u64, own_pieces: u64, enemy_pieces: u64) -> u64 {
     rook_move_mask(bit, own_pieces, enemy_pieces) | bishop_move_mask(bit, own_pieces, enemy_pieces)
 }
@@ -268,7 +314,8 @@ pub fn block_ray(bit: u64, ray: u64, own_pieces: u64, enemy_pieces: u64) -> u64
     (forward_ray & !(enemy_forward_blocker | own_forward_blocker)) | (backward_ray & !(enemy_backward_blocker | own_backward_blocker))
 }
 
-pub fn king_move_mask(bit: u64, own_pieces: u64) -> u64 {
+pub fn king_step_mask(bit: u64, own_pieces: u64) -> u64 {
+    // Computed from scratch - only used to build the king lookup table
 
     let row: u64 = sl(bit) | sr(bit) | bit;
     (row | su(row) | sd(row) & !bit) & !own_pieces
@@ -287,12 +334,50 @@ pub fn king_forward_mask(bit: u64, to_move: u8) -> u64 {
 
 pub fn manhattan_distance(bit1: u64, bit2: u64) -> u32 {
 
-    let b1 = bit1.trailing_zeros();
-    let b2 = bit2.trailing_zeros();
+    let (r1, f1) = get_bit_rf(bit1);
+    let (r2, f2) = get_bit_rf(bit2);
 
-    if b2 > b1 {
-        (b2 - b1) / 8 + (b2 - b1) % 8
-    } else {
-        (b1 - b2) / 8 + (b1 - b2) % 8 
-    }
-}
\ No newline at end of file
+    (r1.abs_diff(r2) + f1.abs_diff(f2)) as u32
+}
+
+pub fn king_zone_mask(bit: u64, to_move: u8) -> u64 {
+    // The squares around the king, plus a further rank in front of it
+    let around: u64 = king_move_mask(bit, 0) | bit;
+    around | bidirectional_shift(king_forward_mask(bit, to_move), 8, to_move)
+}
+
+// Set-wise helpers - unlike sl/sr/su/sd these operate on whole bitboards rather than single bits
+
+pub fn shift_left(bits: u64) -> u64 {
+    (bits >> 1) & !FILE_H
+}
+
+pub fn shift_right(bits: u64) -> u64 {
+    (bits << 1) & !FILE_A
+}
+
+pub fn fill_up(bits: u64) -> u64 {
+    let mut fill: u64 = bits;
+    fill |= fill << 8;
+    fill |= fill << 16;
+    fill |= fill << 32;
+    fill
+}
+
+pub fn fill_down(bits: u64) -> u64 {
+    let mut fill: u64 = bits;
+    fill |= fill >> 8;
+    fill |= fill >> 16;
+    fill |= fill >> 32;
+    fill
+}
+
+pub fn file_fill(bits: u64) -> u64 {
+    fill_up(bits) | fill_down(bits)
+}
+
+pub fn pawn_attacks(pawns: u64, to_move: u8) -> u64 {
+    // Every square attacked by a set of pawns (to_move is 1 for white pawns, 0 for black)
+    let advanced: u64 = bidirectional_shift(pawns, 8, to_move);
+    shift_left(advanced) | shift_right(advanced)
+}
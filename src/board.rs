@@ -1,6 +1,22 @@
-use crate::bit_functions::{bidirectional_shift, bishop_move_mask, coord_to_bit, count_bits, get_bit_rf, get_rank_or_file, iterate_over, king_move_mask, knight_move_mask, move_piece, pawn_capture_mask, queen_move_mask, rook_move_mask};
+use crate::bit_functions::{bidirectional_shift, bishop_move_mask, coord_to_bit, count_bits, get_bit_rf, get_rank_or_file, iterate_over, king_move_mask, knight_move_mask, move_piece, pawn_attacks, pawn_capture_mask, queen_move_mask, rook_move_mask, square_name};
 use crate::constants::*;
 
+// Squares attacked by one side, broken down by attacking piece type
+// Unlike move masks these include squares occupied by friendly pieces, so that defended
+// pieces can be identified
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttackMap {
+    pub pawns:    u64,
+    pub knights:  u64,
+    pub bishops:  u64,
+    pub rooks:    u64,
+    pub queens:   u64,
+    pub king:     u64,
+    pub all:      u64,
+    pub double:   u64, // Squares attacked at least twice
+    pub mobility: u32  // Squares attacked by knights, bishops, rooks and queens that aren't occupied by friendly pieces
+}
+
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Board {
 
@@ -82,31 +98,201 @@ impl Board {
         }
     }
 
+    pub fn from_fen(fen: &str) -> Option<Board> {
+
+        // Halfmove and fullmove counters are optional and ignored, as is the en passant square
+        // since en passant isn't implemented
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+
+        if fields.len() < 4 {
+            return None;
+        }
+
+        // Indexed by position in "PNBRQKpnbrqk"
+        let mut pieces: [u64; 12] = [0; 12];
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+
+        if ranks.len() != 8 {
+            return None;
+        }
+
+        for (i, rank_str) in ranks.iter().enumerate() {
+            let rank: u16 = 7 - i as u16;
+            let mut file: u16 = 0;
+
+            for c in rank_str.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    file += skip as u16;
+                } else {
+                    let index: usize = "PNBRQKpnbrqk".find(c)?;
+                    if file > 7 {
+                        return None;
+                    }
+                    pieces[index] |= coord_to_bit((rank, file));
+                    file += 1;
+                }
+            }
+
+            if file != 8 {
+                return None;
+            }
+        }
+
+        if count_bits(pieces[5]) != 1 || count_bits(pieces[11]) != 1 {
+            return None;
+        }
+
+        let to_move: u8 = match fields[1] {
+            "w" => 1,
+            "b" => 0,
+            _ => return None
+        };
+
+        let castling: &str = fields[2];
+
+        let all_white: u64 = pieces[0..6].iter().fold(0, |acc, p| acc | p);
+        let all_black: u64 = pieces[6..12].iter().fold(0, |acc, p| acc | p);
+
+        let board: Board = Board {
+            white_pawns:     pieces[0],
+            white_knights:   pieces[1],
+            white_bishops:   pieces[2],
+            white_rooks:     pieces[3],
+            white_queens:    pieces[4],
+            white_king:      pieces[5],
+
+            black_pawns:     pieces[6],
+            black_knights:   pieces[7],
+            black_bishops:   pieces[8],
+            black_rooks:     pieces[9],
+            black_queens:    pieces[10],
+            black_king:      pieces[11],
+
+            all_white,
+            all_black,
+            all_pieces:      all_white | all_black,
+
+            white_castle_flags: (castling.contains('Q'), castling.contains('K')),
+            black_castle_flags: (castling.contains('q'), castling.contains('k')),
+
+            white_en_passant_flags: 0x00,
+            black_en_passant_flags: 0x00,
+
+            to_move,
+
+            white_check:     false,
+            black_check:     false,
+
+            white_checkmate: false,
+            black_checkmate: false
+        };
+
+        let (wc, bc) = board.check_check();
+        let (wcm, bcm) = board.check_checkmate((wc, bc));
+
+        Some(Board {
+            white_check:     wc,
+            black_check:     bc,
+            white_checkmate: wcm,
+            black_checkmate: bcm,
+            ..board
+        })
+    }
+
+    pub fn to_fen(&self) -> String {
+
+        // En passant isn't implemented and move counters aren't tracked, so those fields are fixed
+        let pieces: [u64; 12] = [
+            self.white_pawns, self.white_knights, self.white_bishops, self.white_rooks, self.white_queens, self.white_king,
+            self.black_pawns, self.black_knights, self.black_bishops, self.black_rooks, self.black_queens, self.black_king
+        ];
+
+        let mut placement: Vec<String> = vec![];
+
+        for rank in (0..8).rev() {
+            let mut rank_str: String = String::new();
+            let mut empty: u8 = 0;
+
+            for file in 0..8 {
+                match pieces.iter().position(|p| p & coord_to_bit((rank, file)) != 0) {
+                    Some(index) => {
+                        if empty > 0 {
+                            rank_str.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        rank_str.push("PNBRQKpnbrqk".chars().nth(index).unwrap());
+                    },
+                    None => empty += 1
+                }
+            }
+
+            if empty > 0 {
+                rank_str.push_str(&empty.to_string());
+            }
+            placement.push(rank_str);
+        }
+
+        // Castling flags aren't cleared when a rook is captured, so rights are only written
+        // while the king and rook are still on their starting squares
+        let mut castling: String = String::new();
+        if self.white_castle_flags.1 && self.white_king == 0x10 && self.white_rooks & 0x80 != 0 {
+            castling.push('K');
+        }
+        if self.white_castle_flags.0 && self.white_king == 0x10 && self.white_rooks & 0x01 != 0 {
+            castling.push('Q');
+        }
+        if self.black_castle_flags.1 && self.black_king == 0x1000000000000000 && self.black_rooks & 0x8000000000000000 != 0 {
+            castling.push('k');
+        }
+        if self.black_castle_flags.0 && self.black_king == 0x1000000000000000 && self.black_rooks & 0x0100000000000000 != 0 {
+            castling.push('q');
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        format!("{} {} {} - 0 1", placement.join("/"), if self.to_move == 1 {"w"} else {"b"}, castling)
+    }
+
     fn apply_move(&self, from: u64, to: u64) -> Option<Board> {
         
-        // Add rook moves from castling
+        // Add rook moves from castling, which the combined bitboards need to follow too
 
         let mut white_rooks: u64 = self.white_rooks;
         let mut black_rooks: u64 = self.black_rooks;
-
-        if self.to_move == 1 {
-
-            if from == self.white_king {
-                if to == self.white_king << 2 {
-                    white_rooks = move_piece(white_rooks, 0x0000000000000080, self.white_king << 1);
-                } else if to == self.white_king >> 2 {
-                    white_rooks = move_piece(white_rooks, 0x0000000000000001, self.white_king >> 1);
-                }
+        let mut all_white: u64 = self.all_white;
+        let mut all_black: u64 = self.all_black;
+        let mut all_pieces: u64 = self.all_pieces;
+
+        let rook_move: Option<(u64, u64)> = if self.to_move == 1 && from == self.white_king {
+            if to == self.white_king << 2 {
+                Some((0x0000000000000080, self.white_king << 1))
+            } else if to == self.white_king >> 2 {
+                Some((0x0000000000000001, self.white_king >> 1))
+            } else {
+                None
+            }
+        } else if self.to_move == 0 && from == self.black_king {
+            if to == self.black_king << 2 {
+                Some((0x8000000000000000, self.black_king << 1))
+            } else if to == self.black_king >> 2 {
+                Some((0x0100000000000000, self.black_king >> 1))
+            } else {
+                None
             }
         } else {
+            None
+        };
 
-            if from == self.black_king {
-                if to == self.black_king << 2 {
-                    black_rooks = move_piece(black_rooks, 0x8000000000000000, self.black_king << 1);
-                } else if to == self.black_king >> 2 {
-                    black_rooks = move_piece(black_rooks, 0x0100000000000000, self.black_king >> 1);
-                }
+        if let Some((rook_from, rook_to)) = rook_move {
+            if self.to_move == 1 {
+                white_rooks = move_piece(white_rooks, rook_from, rook_to);
+                all_white = move_piece(all_white, rook_from, rook_to);
+            } else {
+                black_rooks = move_piece(black_rooks, rook_from, rook_to);
+                all_black = move_piece(all_black, rook_from, rook_to);
             }
+            all_pieces = move_piece(all_pieces, rook_from, rook_to);
         }
 
         let ib: Board = Board {
@@ -122,9 +308,9 @@ impl Board {
             black_rooks:   move_piece(black_rooks, from, to),
             black_queens:  move_piece(self.black_queens, from, to),
             black_king:    move_piece(self.black_king, from, to),
-            all_white:     move_piece(self.all_white, from, to),
-            all_black:     move_piece(self.all_black, from, to),
-            all_pieces:    move_piece(self.all_pieces, from, to),
+            all_white:     move_piece(all_white, from, to),
+            all_black:     move_piece(all_black, from, to),
+            all_pieces:    move_piece(all_pieces, from, to),
             white_castle_flags: (true, true),
             black_castle_flags: (true, true),
             white_en_passant_flags: 0x00,
@@ -174,6 +360,17 @@ impl Board {
             }
         }
 
+        // Capturing a rook on its starting square also takes away its side's castling
+        if to == 0x0000000000000001 {
+            w_qsc = false;
+        } else if to == 0x0000000000000080 {
+            w_ksc = false;
+        } else if to == 0x0100000000000000 {
+            b_qsc = false;
+        } else if to == 0x8000000000000000 {
+            b_ksc = false;
+        }
+
         // Check promotions
 
         let wp: u64;
@@ -200,32 +397,35 @@ impl Board {
             bq = ib.black_queens;
         }
         
+        // Checks are looked for after promoting, since the new queen may be giving one, and
+        // with the real castle flags so that a checkmated king can't appear to castle away
+        let promoted: Board = Board {
+            white_pawns:  wp,
+            black_pawns:  bp,
+            white_queens: wq,
+            black_queens: bq,
+            white_castle_flags: (w_qsc, w_ksc),
+            black_castle_flags: (b_qsc, b_ksc),
+            ..ib
+        };
+
         // Check for illegally moving into check
-        let (wc, bc): (bool, bool) = ib.check_check();
+        let (wc, bc): (bool, bool) = promoted.check_check();
         if (self.to_move == 1 && wc) || (self.to_move == 0 && bc) {
             return None
         }
 
         // Check checkmates
-        let (wcm, bcm) = ib.check_checkmate((wc, bc));
+        let (wcm, bcm) = promoted.check_checkmate((wc, bc));
 
         let new_board: Board = Board {
-            white_pawns:    wp,
-            black_pawns:    bp,
-            
-            white_queens:    wq,
-            black_queens:    bq,
-
             white_check:     wc,
             black_check:     bc,
 
             white_checkmate: wcm,
             black_checkmate: bcm,
 
-            white_castle_flags: (w_qsc, w_ksc),
-            black_castle_flags: (b_qsc, b_ksc),
-
-            ..ib
+            ..promoted
         };
 
         Some(new_board)
@@ -265,6 +465,74 @@ impl Board {
         (white_checks != 0, black_checks != 0)
     }
 
+    pub fn piece_attacks(&self, piece: u64, piece_type: char, to_move: u8) -> u64 {
+
+        // Passing no own pieces and every other piece as an enemy stops sliding rays on the
+        // first piece of either colour, with that square included
+        let blockers: u64 = self.all_pieces & !piece;
+
+        match piece_type {
+            'p' => pawn_attacks(piece, to_move),
+            'n' => knight_move_mask(piece, 0),
+            'b' => bishop_move_mask(piece, 0, blockers),
+            'r' => rook_move_mask(piece, 0, blockers),
+            'q' => queen_move_mask(piece, 0, blockers),
+            'k' => king_move_mask(piece, 0) & !piece,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn attack_map(&self, to_move: u8) -> AttackMap {
+
+        let own_pieces: u64 = if to_move == 1 {self.all_white} else {self.all_black};
+
+        let mut map: AttackMap = AttackMap {
+            pawns: pawn_attacks(self.get_pieces('p', to_move), to_move),
+            ..AttackMap::default()
+        };
+        map.all = map.pawns;
+
+        for piece_type in ['n', 'b', 'r', 'q', 'k'] {
+
+            let mut type_attacks: u64 = 0;
+
+            for piece in iterate_over(self.get_pieces(piece_type, to_move)) {
+                let attacks: u64 = self.piece_attacks(piece, piece_type, to_move);
+
+                if piece_type != 'k' {
+                    map.mobility += count_bits(attacks & !own_pieces) as u32;
+                }
+
+                map.double |= map.all & attacks;
+                map.all |= attacks;
+                type_attacks |= attacks;
+            }
+
+            match piece_type {
+                'n' => map.knights = type_attacks,
+                'b' => map.bishops = type_attacks,
+                'r' => map.rooks = type_attacks,
+                'q' => map.queens = type_attacks,
+                'k' => map.king = type_attacks,
+                _ => unreachable!()
+            }
+        }
+
+        map
+    }
+
+    pub fn hanging_pieces(&self, to_move: u8, own_attacks: &AttackMap, enemy_attacks: &AttackMap) -> u64 {
+
+        // Pieces (other than the king) belonging to to_move that are attacked and undefended
+        let own_pieces: u64 = if to_move == 1 {
+            self.all_white & !self.white_king
+        } else {
+            self.all_black & !self.black_king
+        };
+
+        own_pieces & enemy_attacks.all & !own_attacks.all
+    }
+
     pub fn check_checkmate(&self, checks: (bool, bool)) -> (bool, bool) {
         
         let mut wcm: bool = false;
@@ -295,6 +563,23 @@ impl Board {
         }
     }
 
+    pub fn piece_type_at(&self, square: u64, to_move: u8) -> Option<char> {
+        // Type of the piece of the given colour on a single square, if there is one
+        ['p', 'n', 'b', 'r', 'q', 'k'].into_iter()
+            .find(|piece_type| self.get_pieces(*piece_type, to_move) & square != 0)
+    }
+
+    pub fn null_move(&self) -> Board {
+        // The same position with the other side to move, as if the side to move had passed
+        // Only meaningful when the side to move isn't in check
+        Board {
+            white_check: false,
+            black_check: false,
+            to_move:     1 - self.to_move,
+            ..*self
+        }
+    }
+
     fn reverse_move_mask(&self, piece_type: char, to_move: u8, to: u64) -> u64 {
 
         let possible_pieces: u64 = self.get_pieces(piece_type, to_move);
@@ -361,7 +646,8 @@ impl Board {
 
     pub fn get_legal_castles(&self) -> (bool, bool) {
 
-        // Checks that castling has not yet happened or been invalidated and all interposing squares are empty
+        // Checks that castling has not yet happened or been invalidated and all interposing squares are empty,
+        // and that the king doesn't castle out of, through or into check
 
         let mut is_castle_legal: (bool, bool) = (false, false);
 
@@ -373,6 +659,17 @@ impl Board {
             is_castle_legal.1 = self.all_pieces & 0x6000000000000000 == 0 && self.black_castle_flags.1;
         }
 
+        if is_castle_legal.0 || is_castle_legal.1 {
+            let attacked: u64 = self.attack_map(self.to_move ^ 1).all;
+            let (queenside, kingside): (u64, u64) = if self.to_move == 1 {
+                (0x000000000000001C, 0x0000000000000070)
+            } else {
+                (0x1C00000000000000, 0x7000000000000000)
+            };
+            is_castle_legal.0 &= attacked & queenside == 0;
+            is_castle_legal.1 &= attacked & kingside == 0;
+        }
+
         is_castle_legal
     }
 
@@ -464,6 +761,136 @@ impl Board {
         self.apply_move(from, to)
     }
 
+    pub fn move_squares(&self, next: &Board) -> (u64, u64) {
+
+        // Recovers the (from, to) squares of the move leading from this board to next, which
+        // must be one of its successors. Castling is reported as the king's move
+        let (own_before, own_after, king_before, king_after) = if self.to_move == 1 {
+            (self.all_white, next.all_white, self.white_king, next.white_king)
+        } else {
+            (self.all_black, next.all_black, self.black_king, next.black_king)
+        };
+
+        if king_before != king_after {
+            return (king_before, king_after);
+        }
+
+        (own_before & !own_after, own_after & !own_before)
+    }
+
+    pub fn find_piece(&self, name: &str) -> Option<u64> {
+
+        // Square of the side to move's piece named like "Nf3", or just "e2" for a pawn
+        let chars: Vec<char> = name.chars().collect();
+        let (piece_type, file, rank) = match chars.as_slice() {
+            [piece, file, rank] => (piece.to_ascii_lowercase(), *file, *rank),
+            [file, rank] => ('p', *file, *rank),
+            _ => return None
+        };
+
+        let square: u64 = get_rank_or_file(file) & get_rank_or_file(rank);
+        if count_bits(square) != 1 {
+            return None;
+        }
+
+        (self.piece_type_at(square, self.to_move) == Some(piece_type)).then_some(square)
+    }
+
+    pub fn parse_squares(text: &str) -> Option<(u64, u64)> {
+
+        // From and to squares written together, eg. "e2e4"
+        let chars: Vec<char> = text.chars().collect();
+        let [from_file, from_rank, to_file, to_rank] = chars.as_slice() else {
+            return None;
+        };
+
+        let from: u64 = get_rank_or_file(*from_file) & get_rank_or_file(*from_rank);
+        let to: u64 = get_rank_or_file(*to_file) & get_rank_or_file(*to_rank);
+
+        (count_bits(from) == 1 && count_bits(to) == 1).then_some((from, to))
+    }
+
+    pub fn destinations(&self, from: u64) -> u64 {
+        // Every square the piece on the given square can legally move to
+        self.generate_move_list().iter()
+            .map(|next| self.move_squares(next))
+            .filter(|(move_from, _)| *move_from == from)
+            .fold(0, |squares, (_, to)| squares | to)
+    }
+
+    pub fn to_san(&self, next: &Board) -> String {
+
+        // Standard algebraic notation for the move leading from this board to next, which must
+        // be one of its successors
+        let (from, to) = self.move_squares(next);
+
+        let piece_type: char = self.piece_type_at(from, self.to_move).unwrap_or('p');
+        let enemy_pieces: u64 = if self.to_move == 1 {self.all_black} else {self.all_white};
+        let is_capture: bool = to & enemy_pieces != 0;
+
+        let mut san: String = String::new();
+
+        if piece_type == 'k' && (from == to << 2 || from == to >> 2) {
+            san.push_str(if to > from {"O-O"} else {"O-O-O"});
+        } else if piece_type == 'p' {
+            if is_capture {
+                san.push_str(&square_name(from)[..1]);
+                san.push('x');
+            }
+            san.push_str(&square_name(to));
+            if to & (RANK_1 | RANK_8) != 0 {
+                san.push_str("=Q");
+            }
+        } else {
+            san.push(piece_type.to_ascii_uppercase());
+
+            // Other pieces of the same type that could also move to this square
+            let others: Vec<u64> = self.generate_move_list().iter()
+                .map(|other| self.move_squares(other))
+                .filter(|(other_from, other_to)| *other_to == to && *other_from != from && self.get_pieces(piece_type, self.to_move) & other_from != 0)
+                .map(|(other_from, _)| other_from)
+                .collect();
+
+            if !others.is_empty() {
+                let (rank, file) = get_bit_rf(from);
+                let same_file: bool = others.iter().any(|other| get_bit_rf(*other).1 == file);
+                let same_rank: bool = others.iter().any(|other| get_bit_rf(*other).0 == rank);
+                if !same_file {
+                    san.push_str(&square_name(from)[..1]);
+                } else if !same_rank {
+                    san.push_str(&square_name(from)[1..]);
+                } else {
+                    san.push_str(&square_name(from));
+                }
+            }
+
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(to));
+        }
+
+        if next.white_checkmate || next.black_checkmate {
+            san.push('#');
+        } else if next.white_check || next.black_check {
+            san.push('+');
+        }
+
+        san
+    }
+
+    pub fn line_to_san(&self, line: &[Board]) -> String {
+
+        // A line of play given as successive boards from this one, eg. a principal variation
+        let mut position: Board = *self;
+
+        line.iter().map(|next| {
+            let san: String = position.to_san(next);
+            position = *next;
+            san
+        }).collect::<Vec<String>>().join(" ")
+    }
+
     pub fn generate_move_list(&self) -> Vec<Board> {
 
         let pawn_start_row: u64;
@@ -607,3 +1034,249 @@ impl Board {
 
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perft(board: &Board, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        board.generate_move_list().iter().map(|next| perft(next, depth - 1)).sum()
+    }
+
+    fn play(board: &Board, from: u64, to: u64) -> Board {
+        board.generate_move_list().into_iter()
+            .find(|next| board.move_squares(next) == (from, to))
+            .expect("Legal move")
+    }
+
+    #[test]
+    fn perft_start_position() {
+        assert_eq!(perft(&Board::new(), 4), 197281);
+    }
+
+    #[test]
+    fn perft_kiwipete_without_en_passant() {
+        // The usual figures are 2039 and 97862, which include 1 and 45 en passant captures and
+        // what follows them. En passant isn't implemented, so these are the counts without it,
+        // as given by shakmaty with en passant moves left out
+        let board: Board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(perft(&board, 1), 48, "Kiwipete has no en passant moves at depth 1");
+        assert_eq!(perft(&board, 2), 2038, "Kiwipete depth 2 without en passant (2039 with it)");
+        assert_eq!(perft(&board, 3), 97766, "Kiwipete depth 3 without en passant (97862 with it)");
+    }
+
+    #[test]
+    fn knight_on_seventh_rank() {
+        // a5, c5, d6 and d8
+        let board: Board = Board::from_fen("4k3/1N6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let destinations: u64 = board.generate_move_list().iter()
+            .map(|next| board.move_squares(next))
+            .filter(|(from, _)| *from == 1 << 49)
+            .fold(0, |squares, (_, to)| squares | to);
+        assert_eq!(destinations, (1 << 32) | (1 << 34) | (1 << 43) | (1 << 59));
+    }
+
+    #[test]
+    fn castling_moves_the_rook_everywhere() {
+        let board: Board = Board::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let castled: Board = play(&board, 1 << 4, 1 << 6);
+
+        assert_eq!(castled.white_rooks, 1 << 5);
+        assert_eq!(castled.all_white, (1 << 5) | (1 << 6));
+        assert_eq!(castled.all_pieces, castled.all_white | castled.all_black);
+    }
+
+    #[test]
+    fn promoting_with_check() {
+        let board: Board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(play(&board, 1 << 49, 1 << 57).black_check);
+    }
+
+    #[test]
+    fn checkmate_without_castling_rights() {
+        // The king and rook are on their starting squares, but castling rights are gone
+        let board: Board = Board::from_fen("q3k3/8/8/8/8/8/3PPP2/4K2R b - - 0 1").unwrap();
+        assert!(play(&board, 1 << 56, 1).white_checkmate);
+    }
+
+    #[test]
+    fn no_castling_out_of_through_or_into_check() {
+        // A black rook on each file in turn - only the squares the king crosses matter, so the
+        // b-file doesn't stop queenside castling
+        let cases: [(&str, (bool, bool)); 6] = [
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", (true, true)),
+            ("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", (true, true)),
+            ("3rk3/8/8/8/8/8/8/R3K2R w KQ - 0 1", (false, true)),
+            ("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", (false, false)),
+            ("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1", (true, false)),
+            ("4k1r1/8/8/8/8/8/8/R3K2R w KQ - 0 1", (true, false))
+        ];
+
+        for (fen, castles) in cases {
+            assert_eq!(Board::from_fen(fen).unwrap().get_legal_castles(), castles, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn capturing_a_rook_at_home_takes_away_castling() {
+        let board: Board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let captured: Board = play(&board, 1 << 7, 1 << 63);
+
+        assert_eq!(captured.black_castle_flags, (true, false));
+        assert_eq!(captured.white_castle_flags, (true, false));
+    }
+
+    #[test]
+    fn destinations_of_named_pieces() {
+        let board: Board = Board::new();
+
+        // e3 and e4, then a3 and c3
+        assert_eq!(board.find_piece("e2").map(|square| board.destinations(square)), Some((1 << 20) | (1 << 28)));
+        assert_eq!(board.find_piece("Nb1").map(|square| board.destinations(square)), Some((1 << 16) | (1 << 18)));
+        assert_eq!(board.find_piece("nb1"), Some(1 << 1));
+    }
+
+    #[test]
+    fn find_piece_rejects_bad_names() {
+        // Off the board, incomplete, or not a piece the side to move has on that square
+        let board: Board = Board::new();
+
+        for name in ["z9", "Nf", "e9", "i2", "", "Nb1c3", "e7", "Ne2", "Qd2"] {
+            assert_eq!(board.find_piece(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn parses_square_pairs() {
+        assert_eq!(Board::parse_squares("e2e4"), Some((1 << 12, 1 << 28)));
+        assert_eq!(Board::parse_squares("h8a1"), Some((1 << 63, 1)));
+
+        for text in ["z9e4", "e2e9", "e2", "e2e4q", "Nf3", ""] {
+            assert_eq!(Board::parse_squares(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn from_fen_start_position() {
+        assert_eq!(Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), Some(Board::new()));
+
+        // The move counters are optional
+        assert_eq!(Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"), Some(Board::new()));
+    }
+
+    #[test]
+    fn from_fen_fields() {
+        let board: Board = Board::from_fen("r3k2r/8/8/8/4n3/8/8/R3K2R b Kq - 3 20").unwrap();
+
+        assert_eq!(board.to_move, 0);
+        assert_eq!(board.white_rooks, 0x81);
+        assert_eq!(board.black_rooks, 0x8100000000000000);
+        assert_eq!(board.black_knights, 1 << 28);
+        assert_eq!(board.all_pieces, 0x9100000010000091);
+        assert_eq!(board.white_castle_flags, (false, true));
+        assert_eq!(board.black_castle_flags, (true, false));
+    }
+
+    #[test]
+    fn from_fen_finds_checks_and_mates() {
+        let mated: Board = Board::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert!(mated.white_check && mated.white_checkmate);
+        assert!(!mated.black_check && !mated.black_checkmate);
+
+        let checked: Board = Board::from_fen("4k3/8/8/8/8/8/8/R3K2r w - - 0 1").unwrap();
+        assert!(checked.white_check && !checked.white_checkmate);
+    }
+
+    #[test]
+    fn from_fen_rejects_bad_fens() {
+        for fen in [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w",        // Too few fields
+            "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",   // Seven ranks
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -", // Nine files
+            "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",  // Seven files
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQXBNR w KQkq -", // Unknown piece
+            "rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ -",   // No black king
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq -"  // Neither side to move
+        ] {
+            assert_eq!(Board::from_fen(fen), None, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1",
+            "8/8/8/4k3/8/8/8/KQ6 w - - 0 1"
+        ] {
+            assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+        }
+
+        // The en passant square and move counters aren't kept, so they come back as the defaults
+        let after_e4: Board = Board::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3").unwrap();
+        assert_eq!(after_e4.to_fen(), "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+    }
+
+    #[test]
+    fn to_fen_after_castling() {
+        let mut board: Board = Board::new();
+        for input in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5", "Kg1"] {
+            board = board.parse_input(&input.to_string()).unwrap();
+        }
+
+        assert_eq!(board.to_fen(), "r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 0 1");
+        assert_eq!(Board::from_fen(&board.to_fen()), Some(board));
+
+        // Rights are only written while the king and rook are at home
+        assert_eq!(Board::from_fen("4k3/8/8/8/8/8/8/4K2R w KQ - 0 1").unwrap().to_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+    }
+
+    #[test]
+    fn san_castling() {
+        let white: Board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(white.to_san(&play(&white, 1 << 4, 1 << 6)), "O-O");
+        assert_eq!(white.to_san(&play(&white, 1 << 4, 1 << 2)), "O-O-O");
+
+        let black: Board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(black.to_san(&play(&black, 1 << 60, 1 << 62)), "O-O");
+        assert_eq!(black.to_san(&play(&black, 1 << 60, 1 << 58)), "O-O-O");
+    }
+
+    #[test]
+    fn san_promotion() {
+        let board: Board = Board::from_fen("2r1k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.to_san(&play(&board, 1 << 49, 1 << 57)), "b8=Q");
+        assert_eq!(board.to_san(&play(&board, 1 << 49, 1 << 58)), "bxc8=Q+");
+    }
+
+    #[test]
+    fn san_disambiguation() {
+        // By file, by rank, and by both when neither alone will do
+        let knights: Board = Board::from_fen("4k3/8/8/8/8/8/8/1N1K1N2 w - - 0 1").unwrap();
+        assert_eq!(knights.to_san(&play(&knights, 1 << 1, 1 << 11)), "Nbd2");
+        assert_eq!(knights.to_san(&play(&knights, 1 << 5, 1 << 11)), "Nfd2");
+        assert_eq!(knights.to_san(&play(&knights, 1 << 1, 1 << 16)), "Na3");
+
+        let rooks: Board = Board::from_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(rooks.to_san(&play(&rooks, 1, 1 << 16)), "R1a3");
+        assert_eq!(rooks.to_san(&play(&rooks, 1 << 32, 1 << 16)), "R5a3");
+
+        let queens: Board = Board::from_fen("4k3/8/8/8/8/Q7/8/Q1Q4K w - - 0 1").unwrap();
+        assert_eq!(queens.to_san(&play(&queens, 1, 1 << 9)), "Qa1b2");
+    }
+
+    #[test]
+    fn san_captures_and_mate() {
+        let board: Board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap();
+        assert_eq!(board.to_san(&play(&board, 1 << 59, 1 << 31)), "Qh4#");
+
+        let board: Board = Board::from_fen("4k3/8/8/3pp3/4P3/5N2/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.to_san(&play(&board, 1 << 28, 1 << 35)), "exd5");
+        assert_eq!(board.to_san(&play(&board, 1 << 21, 1 << 36)), "Nxe5");
+    }
+}
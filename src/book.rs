@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::board::Board;
+use crate::zobrist::polyglot_hash;
+
+// Polyglot opening books
+//
+// A book is a sequence of 16 byte big-endian entries sorted by position key:
+// key (u64), move (u16), weight (u16), learn (u32)
+// Moves pack the to file and rank into bits 0-5, the from file and rank into bits 6-11 and
+// a promotion piece into bits 12-14. Castling is stored as the king capturing its own rook
+
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key:    u64,
+    pub mv:     u16,
+    pub weight: u16,
+    pub learn:  u32
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BookMode {
+    Best,    // Always play the highest weighted move
+    Weighted // Choose randomly in proportion to the weights
+}
+
+pub struct OpeningBook {
+    entries: Vec<BookEntry>,
+    pub mode: BookMode,
+    rng_state: u64
+}
+
+impl OpeningBook {
+    pub fn load(path: &str, mode: BookMode) -> Result<OpeningBook, Box<dyn Error>> {
+
+        let bytes: Vec<u8> = fs::read(path)?;
+
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(format!("{} is not a Polyglot book", path).into());
+        }
+
+        let entries: Vec<BookEntry> = bytes.chunks_exact(ENTRY_SIZE).map(|chunk| {
+            BookEntry {
+                key:    u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                mv:     u16::from_be_bytes(chunk[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(chunk[10..12].try_into().unwrap()),
+                learn:  u32::from_be_bytes(chunk[12..16].try_into().unwrap())
+            }
+        }).collect();
+
+        // Only needs to be unpredictable between games, not cryptographically random
+        let seed: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+
+        Ok(OpeningBook {
+            entries,
+            mode,
+            rng_state: seed | 1
+        })
+    }
+
+    pub fn entries_for(&self, board: &Board) -> &[BookEntry] {
+
+        let key: u64 = polyglot_hash(board);
+        let start: usize = self.entries.partition_point(|entry| entry.key < key);
+        let end: usize = start + self.entries[start..].partition_point(|entry| entry.key == key);
+
+        &self.entries[start..end]
+    }
+
+    pub fn moves(&self, board: &Board) -> Vec<(Board, u16)> {
+
+        // Book moves for this position that are legal here, paired with their weights
+        let move_list: Vec<Board> = board.generate_move_list();
+
+        self.entries_for(board).iter().filter_map(|entry| {
+            let (from, to, promotion) = decode_move(board, entry.mv);
+            // Only promotion to a queen is supported
+            if promotion != 0 && promotion != 4 {
+                return None;
+            }
+            move_list.iter().find(|next| board.move_squares(next) == (from, to)).map(|next| (*next, entry.weight))
+        }).collect()
+    }
+
+    pub fn pick_move(&mut self, board: &Board) -> Option<Board> {
+
+        let moves: Vec<(Board, u16)> = self.moves(board);
+
+        match self.mode {
+            BookMode::Best => moves.iter().max_by_key(|(_, weight)| *weight).map(|(next, _)| *next),
+            BookMode::Weighted => {
+                let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+                if total == 0 {
+                    // Weights are all zero (or there are no moves) - treat every move as equal
+                    return moves.get((self.next_random() % moves.len().max(1) as u64) as usize).map(|(next, _)| *next);
+                }
+                let mut choice: u64 = self.next_random() % total;
+                for (next, weight) in moves.iter() {
+                    if choice < *weight as u64 {
+                        return Some(*next);
+                    }
+                    choice -= *weight as u64;
+                }
+                None
+            }
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        // xorshift64
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        self.rng_state
+    }
+}
+
+fn decode_move(board: &Board, mv: u16) -> (u64, u64, u16) {
+
+    let to: u64 = 1 << (mv & 0x3F);
+    let from: u64 = 1 << ((mv >> 6) & 0x3F);
+    let promotion: u16 = (mv >> 12) & 0x7;
+
+    // Castling is encoded as e1h1, e1a1, e8h8 and e8a8 - translate to the king's destination
+    let king: u64 = if board.to_move == 1 {board.white_king} else {board.black_king};
+    let rooks: u64 = if board.to_move == 1 {board.white_rooks} else {board.black_rooks};
+
+    if from == king && to & rooks != 0 {
+        let castled_to: u64 = if to > from {from << 2} else {from >> 2};
+        return (from, castled_to, promotion);
+    }
+
+    (from, to, promotion)
+}
@@ -0,0 +1,225 @@
+use std::fmt::Write;
+
+use crate::board::Board;
+use crate::bit_functions::{iterate_over, bit_to_coord};
+
+// Positions as SVG diagrams, eg. for pasting into documents
+//
+// Diagrams are standalone files - pieces are drawn as shapes rather than from a chess font, and
+// coordinates are in the generic sans-serif font that every viewer has
+
+const SQUARE: u32 = 45;
+const MARGIN: u32 = 20; // Room for the coordinates to the left of and below the board
+
+const LIGHT: &str = "#f0d9b5";
+const DARK: &str = "#b58863";
+const LAST_MOVE: &str = "#ffd700";
+const ARROW: &str = "#15781b";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiagramOptions {
+    pub flipped:     bool,             // Black at the bottom of the board rather than white
+    pub coordinates: bool,             // Rank numbers and file letters around the board
+    pub last_move:   u64,              // Squares highlighted as the last move's from and to squares
+    pub arrows:      Vec<(u64, u64)>   // From and to squares of arrows drawn over the board
+}
+
+impl Default for DiagramOptions {
+    fn default() -> DiagramOptions {
+        DiagramOptions {
+            flipped:     false,
+            coordinates: true,
+            last_move:   0,
+            arrows:      vec![]
+        }
+    }
+}
+
+pub fn to_svg(board: &Board, options: &DiagramOptions) -> String {
+
+    let left: u32 = if options.coordinates {MARGIN} else {0};
+    let width: u32 = left + 8*SQUARE;
+    let height: u32 = 8*SQUARE + if options.coordinates {MARGIN} else {0};
+
+    // Top left corner of a square, from its (file, rank), the same way round as on screen
+    let corner = |index: (u16, u16)| -> (u32, u32) {
+        let (column, row) = if options.flipped {(7 - index.0, index.1)} else {(index.0, 7 - index.1)};
+        (left + SQUARE * column as u32, SQUARE * row as u32)
+    };
+
+    let mut svg: String = String::new();
+
+    // Writing to a String can't fail
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#, width, height, width, height);
+
+    for square in 0..64 {
+        let index: (u16, u16) = bit_to_coord(1 << square);
+        let (x, y) = corner(index);
+        let light: bool = (index.0 + index.1) % 2 == 1;
+
+        let _ = writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, x, y, SQUARE, SQUARE, if light {LIGHT} else {DARK});
+        if options.last_move & (1 << square) != 0 {
+            let _ = writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="0.45"/>"#, x, y, SQUARE, SQUARE, LAST_MOVE);
+        }
+    }
+
+    if options.coordinates {
+        for i in 0..8 {
+            let (x, y) = corner((i, i));
+            let rank: char = (b'1' + i as u8) as char;
+            let file: char = (b'a' + i as u8) as char;
+
+            let _ = writeln!(svg, r#"<text x="{}" y="{}" font-family="sans-serif" font-size="13" text-anchor="middle" dominant-baseline="central">{}</text>"#, MARGIN / 2, y + SQUARE / 2, rank);
+            let _ = writeln!(svg, r#"<text x="{}" y="{}" font-family="sans-serif" font-size="13" text-anchor="middle" dominant-baseline="central">{}</text>"#, x + SQUARE / 2, 8*SQUARE + MARGIN / 2, file);
+        }
+    }
+
+    let pieces: [(u64, char, char); 12] = [
+        (board.white_pawns, 'p', 'w'), (board.black_pawns, 'p', 'b'),
+        (board.white_bishops, 'b', 'w'), (board.black_bishops, 'b', 'b'),
+        (board.white_knights, 'n', 'w'), (board.black_knights, 'n', 'b'),
+        (board.white_rooks, 'r', 'w'), (board.black_rooks, 'r', 'b'),
+        (board.white_queens, 'q', 'w'), (board.black_queens, 'q', 'b'),
+        (board.white_king, 'k', 'w'), (board.black_king, 'k', 'b')
+    ];
+
+    for (bits, piece, colour) in pieces {
+        // Details are drawn in the opposite colour so they show up on black pieces
+        let (fill, detail) = if colour == 'w' {("#fff", "#000")} else {("#000", "#fff")};
+        let (outline, details) = piece_shape(piece);
+
+        for p in iterate_over(bits) {
+            let (x, y) = corner(bit_to_coord(p));
+            let _ = writeln!(svg, r#"<g transform="translate({} {})" stroke-width="1.5" stroke-linejoin="round">"#, x, y);
+            let _ = writeln!(svg, r##"<g fill="{}" stroke="#000">{}</g>"##, fill, outline);
+            let _ = writeln!(svg, r#"<g fill="{}" stroke="{}">{}</g>"#, detail, detail, details);
+            let _ = writeln!(svg, "</g>");
+        }
+    }
+
+    for &(from, to) in &options.arrows {
+        let _ = writeln!(svg, "{}", arrow(corner(bit_to_coord(from)), corner(bit_to_coord(to))));
+    }
+
+    let _ = writeln!(svg, "</svg>");
+
+    svg
+}
+
+fn arrow(from: (u32, u32), to: (u32, u32)) -> String {
+
+    // A line between the squares' centres, ending in a head that stops short of the centre of
+    // the square pointed to, so that a piece there can still be made out
+    let centre = |corner: (u32, u32)| (corner.0 as f64 + SQUARE as f64 / 2.0, corner.1 as f64 + SQUARE as f64 / 2.0);
+    let (x1, y1) = centre(from);
+    let (x2, y2) = centre(to);
+
+    let length: f64 = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt().max(1.0);
+    let (dx, dy) = ((x2 - x1) / length, (y2 - y1) / length);
+
+    let tip: (f64, f64) = (x2 - dx * 8.0, y2 - dy * 8.0);
+    let base: (f64, f64) = (tip.0 - dx * 16.0, tip.1 - dy * 16.0);
+    let side: (f64, f64) = (-dy * 10.0, dx * 10.0);
+
+    format!(
+        concat!(
+            r#"<g fill="{colour}" stroke="{colour}" opacity="0.8">"#,
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke-width="8"/>"#,
+            r#"<polygon points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" stroke="none"/>"#,
+            "</g>"
+        ),
+        x1, y1, base.0, base.1,
+        tip.0, tip.1, base.0 + side.0, base.1 + side.1, base.0 - side.0, base.1 - side.1,
+        colour = ARROW
+    )
+}
+
+fn piece_shape(piece: char) -> (&'static str, &'static str) {
+
+    // Outline and details of each piece, drawn within a square 45 units across
+    match piece {
+        'p' => (
+            r#"<circle cx="22.5" cy="13" r="5.5"/><path d="M 18 19 H 27 L 28.5 23.5 H 26 L 29 33 H 16 L 19 23.5 H 16.5 Z"/><path d="M 11 39 L 13 33 H 32 L 34 39 Z"/>"#,
+            ""
+        ),
+        'n' => (
+            r#"<path d="M 22 10 C 32.5 11 38.5 18 38 39 H 15 C 15 30 25 32.5 23 18 C 21 21 18 23 16 26 C 14 27.5 11 28 10 26 C 8 24 9 20 11 18 C 15 14 18 12 19 9 C 20 8 21 9 22 10 Z"/>"#,
+            r#"<circle cx="16" cy="16.5" r="1.2" stroke="none"/><path d="M 12 23.5 L 13.5 22.5" fill="none"/>"#
+        ),
+        'b' => (
+            r#"<path d="M 9 39 H 36 V 35 H 9 Z"/><path d="M 15 35 C 15 28 17 25 18 22 C 14 19 15 12 22.5 8 C 30 12 31 19 27 22 C 28 25 30 28 30 35 Z"/><circle cx="22.5" cy="6" r="2.5"/>"#,
+            r#"<path d="M 22.5 13 V 19 M 19.5 16 H 25.5 M 17.5 29 H 27.5" fill="none"/>"#
+        ),
+        'r' => (
+            r#"<path d="M 9 39 H 36 V 35 H 9 Z"/><path d="M 12 35 L 14 31 H 31 L 33 35 Z"/><path d="M 14 31 V 16 H 31 V 31 Z"/><path d="M 11 16 V 9 H 15 V 12 H 20 V 9 H 25 V 12 H 30 V 9 H 34 V 16 Z"/>"#,
+            ""
+        ),
+        'q' => (
+            r#"<path d="M 9 39 H 36 V 35 H 9 Z"/><path d="M 11 35 L 8 14 L 13.5 25 L 15 11 L 19 24 L 22.5 9 L 26 24 L 30 11 L 31.5 25 L 37 14 L 34 35 Z"/><circle cx="8" cy="12.5" r="2.2"/><circle cx="15" cy="9.5" r="2.2"/><circle cx="22.5" cy="7.5" r="2.2"/><circle cx="30" cy="9.5" r="2.2"/><circle cx="37" cy="12.5" r="2.2"/>"#,
+            r#"<path d="M 11.5 31 H 33.5" fill="none"/>"#
+        ),
+        'k' => (
+            r#"<path d="M 9 39 H 36 V 35 H 9 Z"/><path d="M 11 35 C 7 28 6 21 13 19 C 17 18 20 21 22.5 24 C 25 21 28 18 32 19 C 39 21 38 28 34 35 Z"/><path d="M 20.5 6 H 24.5 V 10 H 28 V 14 H 24.5 V 23 H 20.5 V 14 H 17 V 10 H 20.5 Z"/>"#,
+            r#"<path d="M 11.5 31 H 33.5" fill="none"/>"#
+        ),
+        _ => unreachable!("Invalid piece type")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_position() {
+        let svg: String = to_svg(&Board::new(), &DiagramOptions::default());
+
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="380" height="380""#));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<svg").count(), 1);
+        assert_eq!(svg.matches("</svg>").count(), 1);
+
+        assert_eq!(svg.matches("<rect").count(), 64);
+        assert_eq!(svg.matches(r#"<g transform="#).count(), 32);
+        assert_eq!(svg.matches("<text").count(), 16);
+
+        // a1 is dark, in the bottom left corner, with a white rook on it
+        assert!(svg.contains(&format!(r#"<rect x="20" y="315" width="45" height="45" fill="{}"/>"#, DARK)));
+        assert!(svg.contains(&format!(r##"<g transform="translate(20 315)" stroke-width="1.5" stroke-linejoin="round">{}<g fill="#fff" stroke="#000">{}</g>"##, '\n', piece_shape('r').0)));
+    }
+
+    #[test]
+    fn flipped_board() {
+        let options: DiagramOptions = DiagramOptions {flipped: true, ..DiagramOptions::default()};
+        let svg: String = to_svg(&Board::new(), &options);
+
+        // a1 is in the top right corner with its white rook, and h8 in the bottom left with a
+        // black one
+        assert!(svg.contains(&format!(r#"<rect x="335" y="0" width="45" height="45" fill="{}"/>"#, DARK)));
+        assert!(svg.contains(&format!(r##"<g transform="translate(335 0)" stroke-width="1.5" stroke-linejoin="round">{}<g fill="#fff""##, '\n')));
+        assert!(svg.contains(&format!(r##"<g transform="translate(20 315)" stroke-width="1.5" stroke-linejoin="round">{}<g fill="#000""##, '\n')));
+    }
+
+    #[test]
+    fn last_move_and_arrows() {
+        let options: DiagramOptions = DiagramOptions {
+            coordinates: false,
+            last_move:   (1 << 12) | (1 << 28),
+            arrows:      vec![(1 << 6, 1 << 21), (1 << 1, 1 << 18)],
+            ..DiagramOptions::default()
+        };
+        let svg: String = to_svg(&Board::new(), &options);
+
+        // Highlights over e2 and e4, and an arrow from g1 to f3 and from b1 to c3
+        assert_eq!(svg.matches("<rect").count(), 66);
+        assert!(svg.contains(&format!(r#"<rect x="180" y="270" width="45" height="45" fill="{}" fill-opacity="0.45"/>"#, LAST_MOVE)));
+        assert!(svg.contains(&format!(r#"<rect x="180" y="180" width="45" height="45" fill="{}" fill-opacity="0.45"/>"#, LAST_MOVE)));
+
+        assert_eq!(svg.matches("<line").count(), 2);
+        assert_eq!(svg.matches("<polygon").count(), 2);
+        assert!(svg.contains(r#"<line x1="292.5" y1="337.5""#));
+
+        assert_eq!(svg.matches("<text").count(), 0);
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="360" height="360""#));
+    }
+}
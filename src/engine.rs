@@ -1,183 +1,920 @@
-use std::rc::{Rc, Weak};
-use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::board::Board;
-use crate::bit_functions::{count_bits, king_forward_mask};
-use crate::constants::*;
+use crate::constants::{RANK_1, RANK_8};
+use crate::evaluation::{evaluate, EvalParams};
+use crate::tablebase::EndgameTablebase;
+use crate::zobrist::polyglot_hash;
+
+// Multi-threaded alpha/beta search
+//
+// Search is negamax with iterative deepening. Every thread searches the same root position
+// independently ("Lazy SMP"), and the threads only cooperate through a shared transposition
+// table - helpers fill it with results that the main thread then finds instead of searching.
+// To keep helpers from repeating the main thread's work, half of them start a ply deeper and
+// each tries the root moves in a different order, and a helper that completes a deeper
+// iteration than the main thread has its result used instead.
+// Scores inside the search are from the side to move's point of view, and converted back to
+// white's point of view in the result
+//
+// The tree is searched selectively rather than to a fixed width: null-move pruning cuts off
+// positions where even passing would be good enough, and late move reductions search the
+// quiet moves at the end of the ordered move list less deeply, while positions in check are
+// searched a ply deeper. Mates are scored by their distance from the root, so that nearer
+// mates score higher
+
+pub const MAX_DEPTH: usize = 64;
+
+// Deepest ply searched, including check extensions
+const MAX_PLY: usize = 128;
+
+const CHECKMATE: f64 = 9999.0;
+
+// Scores beyond this are mates - CHECKMATE less the number of plies to the mate
+const MATE_BOUND: f64 = CHECKMATE - MAX_PLY as f64;
+
+// How many nodes are searched between checks of the clock
+const NODES_PER_TIME_CHECK: u64 = 2048;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+// Width of the zero-width windows used to test whether a score is above a bound
+const NULL_WINDOW: f64 = 0.001;
+
+// Null-move pruning is only tried with at least this much depth left
+const NULL_MOVE_MIN_DEPTH: usize = 3;
+
+// Moves searched at full depth in each position before late move reductions start
+const LMR_FULL_DEPTH_MOVES: usize = 3;
+const LMR_MIN_DEPTH: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact, // Score is exact
+    Lower, // Search failed high - the score is at least this
+    Upper  // Search failed low - the score is at most this
+}
 
-fn evaluate(board: &Board) -> f64 {
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TtEntry {
+    pub score:      f64,
+    pub depth:      u8,
+    pub bound:      Bound,
+    pub move_index: Option<u8> // Best move as an index into the position's generate_move_list
+}
 
-    // Check checkmates
+impl TtEntry {
 
-    let checks = board.check_check();
-    let checkmates = board.check_checkmate(checks);
+    // Packed into a u64 as: score (f32 bits) in bits 0-31, depth in bits 32-39, bound in bits
+    // 40-41 and move index + 1 (0 for no move) in bits 48-55
+    fn pack(&self) -> u64 {
+        let bound: u64 = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2
+        };
+        let move_index: u64 = self.move_index.map(|index| index as u64 + 1).unwrap_or(0);
 
-    if checkmates.0 == true {
-        return -9999.0
-    } else if checkmates.1 == true {
-        return 9999.0
+        (self.score as f32).to_bits() as u64 | (self.depth as u64) << 32 | bound << 40 | move_index << 48
     }
-    
-    // Count material balance
-    // Slightly unusual structure/casting here is to establish material advantages as float
-    // In case of future changes to method of piece valuation
-    let white_material: f64 = (1 * count_bits(board.white_pawns) + 
-                               3 * count_bits(board.white_bishops) +
-                               3 * count_bits(board.white_knights) + 
-                               5 * count_bits(board.white_rooks) + 
-                               9 * count_bits(board.white_queens)) as f64;
 
-    let black_material: f64 = (1 * count_bits(board.black_pawns) + 
-                               3 * count_bits(board.black_bishops) +
-                               3 * count_bits(board.black_knights) + 
-                               5 * count_bits(board.black_rooks) + 
-                               9 * count_bits(board.black_queens)) as f64;
+    fn unpack(data: u64) -> TtEntry {
+        let bound: Bound = match (data >> 40) & 0x3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper
+        };
+        let move_index: u64 = (data >> 48) & 0xFF;
+
+        TtEntry {
+            score:      f32::from_bits(data as u32) as f64,
+            depth:      (data >> 32) as u8,
+            bound,
+            move_index: if move_index == 0 {None} else {Some((move_index - 1) as u8)}
+        }
+    }
+}
 
-    let material_advantage: f64 = white_material - black_material;
+pub struct TranspositionTable {
+    // Two words per entry: the key xor the data, then the data. Threads read and write entries
+    // without locking, so an entry can be torn by two threads writing at once - the xor means a
+    // torn entry no longer matches its key and is ignored rather than trusted
+    entries: Vec<AtomicU64>,
+    mask:    usize
+}
 
-    // Assess centrality
-    let white_central_pawns: f64 = count_bits(board.white_pawns & CENTRE) as f64;
-    let black_central_pawns: f64 = count_bits(board.black_pawns & CENTRE) as f64;
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
 
-    let centrality_advantage: f64 = 0.1 * (white_central_pawns - black_central_pawns);
+        // Round down to a power of two entries, so the index is just the low bits of the key
+        let requested: usize = (size_mb.max(1) << 20) / 16;
+        let count: usize = 1 << (usize::BITS - 1 - requested.leading_zeros());
 
-    // Encourage attacking play
-    let checks_advantage: f64 = 0.5* (checks.1 as u8 as f64 - checks.0 as u8 as f64);
+        TranspositionTable {
+            entries: (0..count * 2).map(|_| AtomicU64::new(0)).collect(),
+            mask:    count - 1
+        }
+    }
 
-    // Encourage development
-    let wb: Board = Board {
-        to_move : 1,
-        ..*board
-    };
-    let bb: Board = Board {
-        to_move : 0,
-        ..*board
-    };
-    let white_mobility = wb.generate_move_list().len();
-    let black_mobility = bb.generate_move_list().len();
-    let mobility_advantage: f64 = 0.1 * (white_mobility as f64 - black_mobility as f64);
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+
+        let index: usize = (key as usize & self.mask) * 2;
+        let check: u64 = self.entries[index].load(Ordering::Relaxed);
+        let data: u64 = self.entries[index + 1].load(Ordering::Relaxed);
+
+        if data != 0 && check ^ data == key {
+            Some(TtEntry::unpack(data))
+        } else {
+            None
+        }
+    }
 
-    // Encourage king safety
-    let white_castle_potential = board.white_castle_flags.0 as u32 + board.white_castle_flags.1 as u32;
-    let white_king_shield = count_bits(king_forward_mask(board.white_king, 1) & board.white_pawns) as u32;
+    pub fn store(&self, key: u64, entry: TtEntry) {
 
-    let black_castle_potential = board.black_castle_flags.0 as u32 + board.black_castle_flags.1 as u32;
-    let black_king_shield = count_bits(king_forward_mask(board.black_king, 0) & board.black_pawns) as u32;
-    // let white_king_tropism: u32 = iterate_over(board.all_black).into_iter().map(|b| {manhattan_distance(board.white_king, b)}).sum();
-    // let black_king_tropism: u32 = iterate_over(board.all_white).into_iter().map(|b| {manhattan_distance(board.black_king, b)}).sum();
+        let index: usize = (key as usize & self.mask) * 2;
+        let old_data: u64 = self.entries[index + 1].load(Ordering::Relaxed);
+        let old_key: u64 = self.entries[index].load(Ordering::Relaxed) ^ old_data;
 
-    let king_safety_advantage = (white_castle_potential + white_king_shield - black_castle_potential - black_king_shield) as f64;
+        // Keep deeper results for the same position, but always replace other positions
+        if old_key == key && TtEntry::unpack(old_data).depth > entry.depth {
+            return;
+        }
 
-    // Penalise doubled pawns
-    let white_doubled_pawns: u8 = (0..8).collect::<Vec<usize>>().iter().map(|i| (((FILE_A << i) & board.white_pawns) > 1) as u8).sum();
-    let black_doubled_pawns: u8 = (0..8).collect::<Vec<usize>>().iter().map(|i| (((FILE_A << i) & board.black_pawns) > 1) as u8).sum();
+        let data: u64 = entry.pack();
+        self.entries[index].store(key ^ data, Ordering::Relaxed);
+        self.entries[index + 1].store(data, Ordering::Relaxed);
+    }
 
-    let doubled_pawn_advantage: f64 = 0.5 * (white_doubled_pawns - black_doubled_pawns) as f64;
+    pub fn clear(&self) {
+        for entry in self.entries.iter() {
+            entry.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SearchOptions {
+    pub depth:    usize,            // Deepest iteration to search
+    pub time:     Option<Duration>, // Time to stop by, if any
+    pub nodes:    Option<u64>,      // Nodes each thread may search, if limited
+    pub threads:  usize,
+    pub multi_pv: usize             // Number of best root moves to find lines for
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PvLine {
+    pub eval: f64,       // From white's point of view
+    pub pv:   Vec<Board> // Expected line of play, as successive boards from the root
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchInfo {
+    pub depth:   usize,      // Deepest iteration completed so far
+    pub eval:    f64,        // From white's point of view
+    pub nodes:   u64,        // Summed over all threads
+    pub elapsed: Duration,
+    pub pv:      Vec<Board>, // Expected line of play, as successive boards from the root
+    pub lines:   Vec<PvLine> // With MultiPV, the best line for each of the best root moves, best first
+}
 
-    return material_advantage + centrality_advantage + checks_advantage + mobility_advantage + king_safety_advantage + doubled_pawn_advantage;
-} 
+impl SearchInfo {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.elapsed.as_secs_f64().max(0.001)) as u64
+    }
+}
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<Board>, // None if there are no legal moves
+    pub eval:      f64,           // From white's point of view
+    pub depth:     usize,         // Deepest iteration any thread completed
+    pub nodes:     u64,           // Summed over all threads
+    pub elapsed:   Duration,
+    pub pv:        Vec<Board>,    // Principal variation, starting with best_move
+    pub lines:     Vec<PvLine>    // As in SearchInfo
+}
 
-pub struct Node {
-    pub depth: usize,
-    pub board: Board,
-    pub static_eval: f64,
-    pub deep_eval: f64,
-    pub best_next_move: Option<Board>,
-    pub parent: Option<Weak<RefCell<Node>>>,
-    pub children: Vec<Rc<RefCell<Node>>>
+struct SearchThread<'a> {
+    params:      &'a EvalParams,
+    tablebase:   Option<&'a EndgameTablebase>,
+    tt:          &'a TranspositionTable,
+    stop:        &'a AtomicBool,
+    total_nodes: &'a AtomicU64,
+    deadline:    Option<Instant>,
+    start:       Instant,
+    can_stop:    bool, // False until the main thread has a move to fall back on
+    nodes:       u64,
+    id:          usize,
+    node_limit:  Option<u64>,
+    exhausted:   bool, // Has searched as many nodes as node_limit allows
+
+    // Triangular principal variation table - pv[ply] holds the best line found from ply
+    // onwards in the node currently being searched at that ply
+    pv:          Vec<Vec<Board>>,
+
+    // Root moves (as indices into the root's move list) left out of the search, so that with
+    // MultiPV each search of the root finds the best move not already in an earlier line
+    excluded:    Vec<usize>,
+
+    // Killer moves - the last two quiet moves at each ply to cause a cutoff, as (from, to)
+    // squares, which are likely to cause one again in the positions next to it
+    killers:     Vec<[Option<(u64, u64)>; 2]>,
+
+    // Only the main thread reports progress
+    on_progress: Option<&'a mut (dyn FnMut(&SearchInfo) + Send)>,
+    last_info:   Option<SearchInfo>,
+    last_report: Instant
 }
 
-impl Node {
+impl<'a> SearchThread<'a> {
+
+    fn stopped(&self) -> bool {
+        self.can_stop && (self.exhausted || self.stop.load(Ordering::Relaxed))
+    }
+
+    fn report(&mut self) {
+
+        // Resends the latest completed iteration with up to date node counts
+        if let (Some(on_progress), Some(info)) = (self.on_progress.as_mut(), self.last_info.as_mut()) {
+            info.nodes = self.total_nodes.load(Ordering::Relaxed);
+            info.elapsed = self.start.elapsed();
+            on_progress(info);
+        }
+        self.last_report = Instant::now();
+    }
+
+    fn count_node(&mut self) {
+
+        self.nodes += 1;
+
+        if self.nodes.is_multiple_of(NODES_PER_TIME_CHECK) {
+            self.total_nodes.fetch_add(NODES_PER_TIME_CHECK, Ordering::Relaxed);
+
+            if let Some(deadline) = self.deadline {
+                if Instant::now() >= deadline {
+                    self.stop.store(true, Ordering::Relaxed);
+                }
+            }
+
+            // Only this thread stops - the others each have nodes of their own left
+            if let Some(limit) = self.node_limit {
+                self.exhausted = self.nodes >= limit;
+            }
 
-    pub fn new(board: &Board) -> Node {
-        let eval = evaluate(board);
-        Node {
-            depth: 0,
-            board: board.clone(),
-            static_eval: eval,
-            deep_eval: eval,
-            best_next_move: None,
-            parent: None,
-            children: vec![]
+            if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+                self.report();
+            }
         }
     }
 
-    fn add_child(parent: &mut Node, child: Rc<RefCell<Node>>) {
-        parent.children.push(child);
+    fn iterative_deepening(&mut self, board: &Board, options: &SearchOptions, id: usize) -> (Vec<PvLine>, usize) {
+
+        // Returns the lines and depth of the deepest completed iteration, best line first
+        // Thread 0 is the main thread and the rest are helpers
+        let is_main: bool = id == 0;
+        self.id = id;
+        let move_list: Vec<Board> = board.generate_move_list();
+        let side: f64 = if board.to_move == 1 {1.0} else {-1.0};
+
+        let mut best_lines: Vec<PvLine> = vec![PvLine {
+            eval: side * evaluate(board, self.params),
+            pv:   move_list.first().copied().into_iter().collect()
+        }];
+        let mut completed_depth: usize = 0;
+
+        if move_list.is_empty() {
+            return (best_lines, 0);
+        }
+
+        // Helpers only search for the best move, to fill the transposition table
+        let multi_pv: usize = if is_main {options.multi_pv.clamp(1, move_list.len())} else {1};
+
+        self.can_stop = !is_main;
+
+        'deepening: for depth in 1..=options.depth {
+
+            // Helpers on odd threads start a ply deeper, so that the threads spread out over
+            // different depths rather than all searching the same tree in step
+            if !is_main && depth < 1 + id % 2 {
+                continue;
+            }
+
+            let mut lines: Vec<PvLine> = vec![];
+            self.excluded.clear();
+
+            for _ in 0..multi_pv {
+
+                let score: f64 = self.negamax(board, depth, 0, f64::NEG_INFINITY, f64::INFINITY, false);
+
+                // An unfinished iteration can't be trusted, so the previous one's lines stand
+                if self.stopped() {
+                    break 'deepening;
+                }
+
+                let pv: Vec<Board> = self.complete_pv(board, self.pv[0].clone(), depth);
+                if let Some(index) = pv.first().and_then(|best| move_list.iter().position(|next| next == best)) {
+                    self.excluded.push(index);
+                }
+                lines.push(PvLine {
+                    eval: side * score,
+                    pv
+                });
+            }
+
+            // Each line is the best of the moves left, but a later search can still find a better
+            // score than an earlier one through the transposition table
+            lines.sort_by(|a, b| (side * b.eval).total_cmp(&(side * a.eval)));
+
+            best_lines = lines;
+            completed_depth = depth;
+            self.can_stop = true;
+
+            self.last_info = Some(SearchInfo {
+                depth,
+                eval:    best_lines[0].eval,
+                nodes:   0,
+                elapsed: Duration::ZERO,
+                pv:      best_lines[0].pv.clone(),
+                lines:   best_lines.clone()
+            });
+            self.report();
+
+            if self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            // Each iteration takes several times longer than the last, so don't start one
+            // that is unlikely to finish
+            if let Some(deadline) = self.deadline {
+                if is_main && self.start.elapsed() * 2 > deadline.saturating_duration_since(self.start) {
+                    break;
+                }
+            }
+            if let Some(limit) = self.node_limit {
+                if is_main && self.nodes * 2 > limit {
+                    break;
+                }
+            }
+        }
+
+        self.excluded.clear();
+
+        (best_lines, completed_depth)
     }
 
-    pub fn process_node_cell(node_cell: &Rc<RefCell<Node>>, depth: usize) {
+    fn complete_pv(&self, root: &Board, mut pv: Vec<Board>, depth: usize) -> Vec<Board> {
+
+        // Lines are cut short wherever a transposition table hit ended the search early, so are
+        // carried on from the best moves stored in the table
+        let mut position: Board = *pv.last().unwrap_or(root);
 
-        // Carry out deep a/b eval and find best next move for node
+        while pv.len() < depth {
+            let next: Option<Board> = self.tt.probe(polyglot_hash(&position))
+                .and_then(|entry| entry.move_index)
+                .and_then(|index| position.generate_move_list().get(index as usize).copied());
 
-        let (deep_eval, next_move) = Node::get_ab_eval(node_cell, depth, f64::MAX, f64::MIN);
-        let node = &mut *node_cell.borrow_mut();
-        node.deep_eval = deep_eval;
-        node.best_next_move = Some(next_move);
+            match next {
+                Some(next) => {
+                    pv.push(next);
+                    position = next;
+                },
+                None => break
+            }
+        }
+
+        pv
     }
 
-    pub fn get_ab_eval(node_cell: &Rc<RefCell<Node>>, depth: usize, alpha: f64, beta: f64) -> (f64, Board) {
+    fn negamax(&mut self, board: &Board, depth: usize, ply: usize, mut alpha: f64, beta: f64, allow_null: bool) -> f64 {
+
+        self.count_node();
+
+        if self.pv.len() <= ply + 1 {
+            self.pv.resize(ply + 2, vec![]);
+            self.killers.resize(ply + 2, [None; 2]);
+        }
+        self.pv[ply].clear();
 
-        let local_alpha: f64 = alpha;
-        let local_beta: f64 = beta;
+        if self.stopped() {
+            return 0.0;
+        }
+        let side: f64 = if board.to_move == 1 {1.0} else {-1.0};
 
-        let node = &mut *node_cell.borrow_mut();
+        // The side to move has been checkmated
+        if board.white_checkmate || board.black_checkmate {
+            return -(CHECKMATE - ply as f64);
+        }
 
-        if node.depth == depth {
-            // Eval at stopping depth is just static eval
-            return (node.static_eval, node.board);
+        // Mate distance pruning - nothing here can beat being mated right now, or do better than
+        // mating on the next move, so when a mate already found nearer the root is at least as
+        // good, there's no need to search further
+        let beta: f64 = if ply > 0 {
+            alpha = alpha.max(-(CHECKMATE - ply as f64));
+            let beta: f64 = beta.min(CHECKMATE - (ply + 1) as f64);
+            if alpha >= beta {
+                return alpha;
+            }
+            beta
         } else {
+            beta
+        };
+
+        // Positions covered by the tablebases have an exact score, so need no further search
+        // The root is left to search (or to the tablebase's own move choice) so that a move is found
+        if ply > 0 {
+            if let Some(score) = self.tablebase.and_then(|tb| tb.score(board)) {
+                return side * score;
+            }
+        }
 
-            let mut best_eval: f64 = if node.board.to_move == 1 {f64::MIN} else {f64::MAX};
-            let mut best_board: Board = Board::new();
+        let in_check: bool = if board.to_move == 1 {board.white_check} else {board.black_check};
 
-            let move_list: Vec<Board> = node.board.generate_move_list();
+        // Check extensions - positions in check are searched a ply deeper, since the replies are
+        // few and forced, and stopping partway through a series of checks misses the mates at
+        // the end of them
+        let depth: usize = if in_check {depth + 1} else {depth};
 
-            for board in move_list.iter().rev() {
-                let static_eval: f64 = evaluate(&board);
+        if depth == 0 || ply >= MAX_PLY {
+            return side * evaluate(board, self.params);
+        }
 
-                let tmpcell = Rc::new(RefCell::new(
-                    Node {
-                        depth: node.depth + 1,
-                        board: board.clone(),
-                        static_eval: static_eval,
-                        deep_eval: 0.0,
-                        best_next_move: None,
-                        parent: Some(Rc::downgrade(&node_cell)),
-                        children: vec![]
-                    }
-                ));
-
-                // Alpha-beta eval
-                // White won't choose a move that black has a powerful response to and vice-versa
-                // Neither side will play hope chess, will select moves assuming the other side
-                // will choose best refutation
-
-                if node.board.to_move == 1 {
-                    let (move_eval, _) = Node::get_ab_eval(&tmpcell, depth, f64::MAX, best_eval);
-                    Node::add_child(node, tmpcell);
-                    if move_eval >= best_eval {
-                        best_eval = move_eval;
-                        best_board = *board;
-                    }
-                    if move_eval > local_alpha {
-                        break
-                    }
+        let key: u64 = polyglot_hash(board);
+        let tt_entry: Option<TtEntry> = self.tt.probe(key);
+
+        if let Some(entry) = tt_entry {
+            let score: f64 = score_from_tt(entry.score, ply);
+            if ply > 0 && entry.depth as usize >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        // Null-move pruning - if passing and letting the opponent move twice still scores at least
+        // beta in a shallower search, a real move almost certainly would too. Passing is never
+        // allowed twice in a row, and not when in check, where it would be illegal. With only
+        // pawns left, positions where every move makes things worse (zugzwang) are common enough
+        // that passing is no longer a safe lower bound, so it isn't tried there either
+        if allow_null
+            && ply > 0
+            && depth >= NULL_MOVE_MIN_DEPTH
+            && !in_check
+            && has_pieces(board)
+            && beta.abs() < MATE_BOUND
+            && side * evaluate(board, self.params) >= beta {
+
+            let reduction: usize = if depth > 6 {3} else {2};
+            let score: f64 = -self.negamax(&board.null_move(), depth - 1 - reduction, ply + 1, -beta, -beta + NULL_WINDOW, false);
+
+            if self.stopped() {
+                return 0.0;
+            }
+            if score >= beta {
+                return beta;
+            }
+        }
+
+        let move_list: Vec<Board> = board.generate_move_list();
+
+        // No legal moves without being in checkmate is stalemate
+        if move_list.is_empty() {
+            return 0.0;
+        }
+
+        // Moves most likely to cause a cutoff first - the best move from an earlier search of
+        // this position, then captures, then killer moves. Ties keep the reverse of generation order
+        let tt_move: Option<usize> = tt_entry.and_then(|entry| entry.move_index).map(|index| index as usize).filter(|index| *index < move_list.len());
+        let killers: [Option<(u64, u64)>; 2] = self.killers[ply];
 
+        let mut order: Vec<(i32, usize)> = (0..move_list.len()).rev()
+            .filter(|index| ply > 0 || !self.excluded.contains(index))
+            .map(|index| {
+                let score: i32 = if Some(index) == tt_move {
+                    TT_MOVE_ORDER
                 } else {
-                    let (move_eval, _) = Node::get_ab_eval(&tmpcell, depth, best_eval, f64::MIN);
-                    Node::add_child(node, tmpcell);
-                    if move_eval <= best_eval {
-                        best_eval = move_eval;
-                        best_board = *board;
-                    }
-                    if move_eval < local_beta {
-                        break
+                    move_order_score(board, &move_list[index], &killers)
+                };
+                (score, index)
+            })
+            .collect();
+        // Each helper starts from a different root move, so that among moves with equal scores
+        // (the stable sort keeps their order) helpers search different ones first
+        if ply == 0 && self.id > 0 {
+            let shift: usize = self.id * 3 % order.len();
+            order.rotate_left(shift);
+        }
+        order.sort_by_key(|(score, _)| -score);
+
+        let original_alpha: f64 = alpha;
+        let mut best_score: f64 = f64::NEG_INFINITY;
+        let mut best_index: usize = order[0].1;
+
+        for (moves_searched, (order_score, index)) in order.into_iter().enumerate() {
+
+            let next: &Board = &move_list[index];
+            let gives_check: bool = next.white_check || next.black_check;
+
+            // Late move reductions - quiet moves this far down the ordering rarely turn out best,
+            // so they are first searched less deeply with a zero-width window just to show they
+            // can't beat alpha. Any that might are searched again at full depth
+            let reduction: usize = if depth >= LMR_MIN_DEPTH
+                && moves_searched >= LMR_FULL_DEPTH_MOVES
+                && order_score == 0
+                && !in_check
+                && !gives_check
+                && alpha.is_finite() {
+                if moves_searched >= 2 * LMR_FULL_DEPTH_MOVES {2} else {1}
+            } else {
+                0
+            };
+
+            let mut score: f64 = f64::NEG_INFINITY;
+            if reduction > 0 {
+                score = -self.negamax(next, depth - 1 - reduction, ply + 1, -alpha - NULL_WINDOW, -alpha, true);
+            }
+            if reduction == 0 || score > alpha {
+                score = -self.negamax(next, depth - 1, ply + 1, -beta, -alpha, true);
+            }
+
+            if self.stopped() {
+                return 0.0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_index = index;
+            }
+            if score > alpha {
+                alpha = score;
+
+                // This move and the line below it form the new best line from here
+                let (line, rest) = self.pv.split_at_mut(ply + 1);
+                line[ply].clear();
+                line[ply].push(move_list[index]);
+                line[ply].extend_from_slice(&rest[0]);
+            }
+            if alpha >= beta {
+                if order_score == 0 || KILLER_MOVE_ORDER.contains(&order_score) {
+                    let squares: (u64, u64) = board.move_squares(next);
+                    if self.killers[ply][0] != Some(squares) {
+                        self.killers[ply] = [Some(squares), self.killers[ply][0]];
                     }
                 }
+                break;
+            }
+        }
+
+        let bound: Bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+
+        // With moves left out the root's result isn't the position's true score
+        if ply > 0 || self.excluded.is_empty() {
+            self.tt.store(key, TtEntry {
+                score:      score_to_tt(best_score, ply),
+                depth:      depth.min(u8::MAX as usize) as u8,
+                bound,
+                move_index: Some(best_index as u8)
+            });
+        }
+
+        best_score
+    }
+}
+
+// Mate scores count plies from the root, but the table is shared between searches from different
+// roots, so it stores them counted from the position itself instead
+fn score_to_tt(score: f64, ply: usize) -> f64 {
+    if score > MATE_BOUND {
+        score + ply as f64
+    } else if score < -MATE_BOUND {
+        score - ply as f64
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: f64, ply: usize) -> f64 {
+    if score > MATE_BOUND {
+        score - ply as f64
+    } else if score < -MATE_BOUND {
+        score + ply as f64
+    } else {
+        score
+    }
+}
+
+pub fn mate_distance(eval: f64) -> Option<i32> {
+    // Moves until mate for a search score, positive when white is mating, or None if the score
+    // isn't a mate
+    if eval.abs() <= MATE_BOUND {
+        return None;
+    }
+    let moves: i32 = ((CHECKMATE - eval.abs()).round() as i32 + 1) / 2;
+    Some(if eval > 0.0 {moves} else {-moves})
+}
+
+pub fn format_eval(eval: f64) -> String {
+    // A search score for display, eg. "+0.45", or "#3" / "#-3" for white / black mating in 3
+    match mate_distance(eval) {
+        Some(moves) => format!("#{}", moves),
+        None => format!("{:+.2}", eval)
+    }
+}
+
+// Move ordering scores - anything scoring 0 is a quiet move
+const TT_MOVE_ORDER: i32 = 10000;
+const CAPTURE_ORDER: i32 = 1000;
+const PROMOTION_ORDER: i32 = 900;
+const KILLER_MOVE_ORDER: [i32; 2] = [500, 400];
+
+fn order_value(piece_type: char) -> i32 {
+    match piece_type {
+        'p' => 1,
+        'n' | 'b' => 3,
+        'r' => 5,
+        'q' => 9,
+        _ => 10
+    }
+}
+
+fn move_order_score(board: &Board, next: &Board, killers: &[Option<(u64, u64)>; 2]) -> i32 {
+
+    let (from, to) = board.move_squares(next);
+    let attacker: char = board.piece_type_at(from, board.to_move).unwrap_or('p');
+
+    // Captures of the most valuable victims first, and by the least valuable attacker among those
+    if let Some(victim) = board.piece_type_at(to, 1 - board.to_move) {
+        return CAPTURE_ORDER + 10 * order_value(victim) - order_value(attacker);
+    }
+    if attacker == 'p' && to & (RANK_1 | RANK_8) != 0 {
+        return PROMOTION_ORDER;
+    }
+
+    match killers.iter().position(|killer| *killer == Some((from, to))) {
+        Some(slot) => KILLER_MOVE_ORDER[slot],
+        None => 0
+    }
+}
+
+fn has_pieces(board: &Board) -> bool {
+    // Whether the side to move has anything besides pawns and its king
+    if board.to_move == 1 {
+        board.white_knights | board.white_bishops | board.white_rooks | board.white_queens != 0
+    } else {
+        board.black_knights | board.black_bishops | board.black_rooks | board.black_queens != 0
+    }
+}
+
+pub fn search(board: &Board, params: &EvalParams, tablebase: Option<&EndgameTablebase>, tt: &TranspositionTable, options: &SearchOptions, stop: &AtomicBool, on_progress: &mut (dyn FnMut(&SearchInfo) + Send)) -> SearchResult {
+
+    // Searches until the options' depth or time runs out, or until stop is set from elsewhere,
+    // calling on_progress after each iteration and periodically while searching
+    let start: Instant = Instant::now();
+    let total_nodes: AtomicU64 = AtomicU64::new(0);
+    let deadline: Option<Instant> = options.time.map(|time| start + time);
+
+    let new_thread = || SearchThread {
+        params,
+        tablebase,
+        tt,
+        stop,
+        total_nodes: &total_nodes,
+        deadline,
+        start,
+        can_stop:    false,
+        nodes:       0,
+        id:          0,
+        node_limit:  options.nodes,
+        exhausted:   false,
+        pv:          vec![],
+        excluded:    vec![],
+        killers:     vec![],
+        on_progress: None,
+        last_info:   None,
+        last_report: start
+    };
+
+    thread::scope(|scope| {
+
+        let helpers: Vec<thread::ScopedJoinHandle<(Vec<PvLine>, usize)>> = (1..options.threads.max(1)).map(|id| {
+            let mut helper: SearchThread = new_thread();
+            scope.spawn(move || {
+                let result: (Vec<PvLine>, usize) = helper.iterative_deepening(board, options, id);
+                helper.total_nodes.fetch_add(helper.nodes % NODES_PER_TIME_CHECK, Ordering::Relaxed);
+                result
+            })
+        }).collect();
+
+        let mut main_thread: SearchThread = new_thread();
+        main_thread.on_progress = Some(on_progress);
+        let (mut lines, mut depth) = main_thread.iterative_deepening(board, options, 0);
+        total_nodes.fetch_add(main_thread.nodes % NODES_PER_TIME_CHECK, Ordering::Relaxed);
+
+        stop.store(true, Ordering::Relaxed);
+        for helper in helpers {
+            let (helper_lines, helper_depth) = helper.join().expect("Search thread panicked");
+
+            // A helper's deeper iteration is the better result, though helpers only find one
+            // line so can't stand in for the main thread's with MultiPV
+            if helper_depth > depth && options.multi_pv <= 1 {
+                lines = helper_lines;
+                depth = helper_depth;
             }
-            return (best_eval, best_board)
         }
+
+        SearchResult {
+            best_move: lines[0].pv.first().copied(),
+            eval:      lines[0].eval,
+            depth,
+            nodes:     total_nodes.load(Ordering::Relaxed),
+            elapsed:   start.elapsed(),
+            pv:        lines[0].pv.clone(),
+            lines
+        }
+    })
+}
+
+pub struct SearchHandle {
+    stop:     Arc<AtomicBool>,
+    progress: Receiver<SearchInfo>,
+    thread:   thread::JoinHandle<SearchResult>
+}
+
+impl SearchHandle {
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
     }
-}
\ No newline at end of file
+
+    pub fn progress(&self) -> Option<SearchInfo> {
+        // Next progress report not yet collected, if any
+        self.progress.try_recv().ok()
+    }
+
+    pub fn wait(self) -> SearchResult {
+        self.thread.join().expect("Search thread panicked")
+    }
+}
+
+pub fn start_search(board: Board, params: EvalParams, tablebase: Option<Arc<EndgameTablebase>>, tt: Arc<TranspositionTable>, options: SearchOptions) -> SearchHandle {
+
+    // Runs search on a background thread, so that the caller stays responsive and can stop it
+    let stop: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let (sender, progress) = mpsc::channel::<SearchInfo>();
+
+    let thread_stop: Arc<AtomicBool> = Arc::clone(&stop);
+    let thread = thread::spawn(move || {
+        // The receiver may have been dropped if the caller lost interest, which is fine
+        search(&board, &params, tablebase.as_deref(), &tt, &options, &thread_stop, &mut |info: &SearchInfo| {
+            let _ = sender.send(info.clone());
+        })
+    });
+
+    SearchHandle {
+        stop,
+        progress,
+        thread
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MateResult {
+    pub line:    Option<Vec<Board>>, // Mating line with the longest defence, if a mate was found
+    pub moves:   usize,              // Mates up to this many moves have been ruled out, if none was found
+    pub nodes:   u64,
+    pub elapsed: Duration
+}
+
+struct MateSearch<'a> {
+    stop:     &'a AtomicBool,
+    nodes:    u64,
+
+    // The most moves each position is known not to have a forced mate within, with the
+    // attacking side to move
+    refuted:  HashMap<u64, usize>
+}
+
+impl<'a> MateSearch<'a> {
+
+    fn attack(&mut self, board: &Board, moves: usize) -> Option<Board> {
+
+        // A move forcing mate within the given number of moves, if there is one
+        self.nodes += 1;
+        if self.stop.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let key: u64 = polyglot_hash(board);
+        if self.refuted.get(&key).is_some_and(|refuted| *refuted >= moves) {
+            return None;
+        }
+
+        // Checks first, since they leave the fewest defences
+        let mut move_list: Vec<Board> = board.generate_move_list();
+        move_list.sort_by_key(|next| !(next.white_check || next.black_check));
+
+        for next in move_list {
+            if next.white_checkmate || next.black_checkmate || (moves > 1 && self.defend(&next, moves - 1)) {
+                return Some(next);
+            }
+        }
+
+        // A search cut short hasn't ruled anything out
+        if !self.stop.load(Ordering::Relaxed) {
+            self.refuted.insert(key, moves);
+        }
+        None
+    }
+
+    fn defend(&mut self, board: &Board, moves: usize) -> bool {
+
+        // Whether every defence still allows mate within the given number of moves. Having no
+        // moves without being checkmated is stalemate, which is a defence
+        let move_list: Vec<Board> = board.generate_move_list();
+        !move_list.is_empty() && move_list.iter().all(|next| self.attack(next, moves).is_some())
+    }
+
+    fn shortest_mate(&mut self, board: &Board, most: usize) -> Option<(Board, usize)> {
+        (1..=most).find_map(|moves| self.attack(board, moves).map(|next| (next, moves)))
+    }
+
+    fn mating_line(&mut self, board: &Board, moves: usize) -> Vec<Board> {
+
+        // Plays out a mate known to take exactly the given number of moves, choosing at each
+        // turn the defence that holds out the longest
+        let mut line: Vec<Board> = vec![];
+        let mut position: Board = *board;
+        let mut moves: usize = moves;
+
+        while let Some(next) = self.attack(&position, moves) {
+            line.push(next);
+
+            let defence: Option<(Board, usize)> = next.generate_move_list().into_iter()
+                .filter_map(|reply| self.shortest_mate(&reply, moves - 1).map(|(_, needed)| (reply, needed)))
+                .max_by_key(|(_, needed)| *needed);
+
+            match defence {
+                Some((reply, needed)) => {
+                    line.push(reply);
+                    position = reply;
+                    moves = needed;
+                },
+                None => break
+            }
+        }
+
+        line
+    }
+}
+
+pub fn mate_search(board: &Board, moves: usize, stop: &AtomicBool) -> MateResult {
+
+    // Looks for a forced mate for the side to move within the given number of moves, trying
+    // each number of moves in turn so that the shortest mate is found. Unlike search this is
+    // exhaustive - nothing is pruned and positions are never evaluated, so a mate is found if
+    // there is one, but only for small numbers of moves
+    let start: Instant = Instant::now();
+    let mut solver: MateSearch = MateSearch {
+        stop,
+        nodes:   0,
+        refuted: HashMap::new()
+    };
+
+    let mut line: Option<Vec<Board>> = None;
+    let mut ruled_out: usize = 0;
+
+    for depth in 1..=moves {
+        if solver.attack(board, depth).is_some() {
+            line = Some(solver.mating_line(board, depth));
+            break;
+        }
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        ruled_out = depth;
+    }
+
+    MateResult {
+        line,
+        moves:   ruled_out,
+        nodes:   solver.nodes,
+        elapsed: start.elapsed()
+    }
+}
@@ -0,0 +1,403 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs;
+
+use crate::board::{AttackMap, Board};
+use crate::bit_functions::{bidirectional_shift, count_bits, file_fill, get_bit_rf, iterate_over, king_forward_mask, king_zone_mask, manhattan_distance, sl, sr};
+use crate::constants::*;
+use crate::pawns::{PawnFeatures, PawnHashTable, PawnStructure};
+
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+thread_local! {
+    static PAWN_TABLE: RefCell<PawnHashTable> = RefCell::new(PawnHashTable::new(PAWN_TABLE_SIZE));
+}
+
+fn pawn_structure_score(features: &PawnFeatures, params: &EvalParams) -> f64 {
+
+    let passed_bonus: f64 = features.passed.iter().zip(params.passed_pawn.iter()).map(|(n, bonus)| *n as f64 * bonus).sum();
+
+    passed_bonus + params.connected_pawn * features.connected as f64 -
+        params.doubled_pawn * features.doubled as f64 -
+        params.isolated_pawn * features.isolated as f64 -
+        params.backward_pawn * features.backward as f64
+}
+
+fn game_phase(board: &Board) -> f64 {
+
+    // 1.0 with all pieces on the board, falling to 0.0 with only kings and pawns
+    let phase: u8 = count_bits(board.white_knights | board.white_bishops | board.black_knights | board.black_bishops) +
+                    2 * count_bits(board.white_rooks | board.black_rooks) +
+                    4 * count_bits(board.white_queens | board.black_queens);
+
+    (phase.min(24) as f64) / 24.0
+}
+
+fn king_safety(board: &Board, to_move: u8, enemy_attacks: &AttackMap, params: &EvalParams) -> f64 {
+
+    let (king, own_pawns, enemy_pawns, castle_flags) = if to_move == 1 {
+        (board.white_king, board.white_pawns, board.black_pawns, board.white_castle_flags)
+    } else {
+        (board.black_king, board.black_pawns, board.white_pawns, board.black_castle_flags)
+    };
+
+    if king == 0 {
+        return 0.0;
+    }
+
+    let castle_potential: f64 = params.castle_right * (castle_flags.0 as u8 + castle_flags.1 as u8) as f64;
+
+    // Attacks into the king zone, weighted by attacker - scaled quadratically so that
+    // several pieces joining an attack is far more dangerous than one
+    let zone: u64 = king_zone_mask(king, to_move);
+    let attack_units: f64 = params.king_attack_knight * count_bits(zone & enemy_attacks.knights) as f64 +
+                            params.king_attack_bishop * count_bits(zone & enemy_attacks.bishops) as f64 +
+                            params.king_attack_rook * count_bits(zone & enemy_attacks.rooks) as f64 +
+                            params.king_attack_queen * count_bits(zone & enemy_attacks.queens) as f64;
+    let attack_penalty: f64 = (params.king_attack_scale * attack_units * attack_units).min(params.king_attack_cap);
+
+    // Pawn shield directly in front of the king and one rank further
+    let shield_front: u64 = king_forward_mask(king, to_move);
+    let shield_back: u64 = bidirectional_shift(shield_front, 8, to_move);
+    let shield: f64 = params.pawn_shield_front * count_bits(shield_front & own_pawns) as f64 + params.pawn_shield_back * count_bits(shield_back & own_pawns) as f64;
+
+    // Open and half-open files on and beside the king's file
+    let king_files: u64 = file_fill(sl(king) | sr(king) | king);
+    let mut open_file_penalty: f64 = 0.0;
+    for file in (0..8).map(|i| FILE_A << i).filter(|file| file & king_files != 0) {
+        if file & own_pawns == 0 {
+            open_file_penalty += if file & enemy_pawns == 0 {params.open_file} else {params.half_open_file};
+        }
+    }
+
+    // Enemy pawns advancing on the king's files
+    let king_rank: u8 = get_bit_rf(king).0;
+    let mut storm_penalty: f64 = 0.0;
+    for pawn in iterate_over(enemy_pawns & king_files) {
+        let pawn_rank: u8 = get_bit_rf(pawn).0;
+        let in_front: bool = if to_move == 1 {pawn_rank > king_rank} else {pawn_rank < king_rank};
+        let distance: u8 = pawn_rank.abs_diff(king_rank);
+        if in_front && distance <= 3 {
+            storm_penalty += params.pawn_storm * (4 - distance) as f64;
+        }
+    }
+
+    // Tropism - enemy pieces close to the king, with the queen counting double
+    let (enemy_minors_and_rooks, enemy_queens) = if to_move == 1 {
+        (board.black_knights | board.black_bishops | board.black_rooks, board.black_queens)
+    } else {
+        (board.white_knights | board.white_bishops | board.white_rooks, board.white_queens)
+    };
+    let tropism: u32 = iterate_over(enemy_minors_and_rooks).into_iter().map(|b| 14 - manhattan_distance(king, b)).sum::<u32>() +
+                       iterate_over(enemy_queens).into_iter().map(|b| 2 * (14 - manhattan_distance(king, b))).sum::<u32>();
+    let tropism_penalty: f64 = params.tropism * tropism as f64;
+
+    castle_potential + shield - attack_penalty - open_file_penalty - storm_penalty - tropism_penalty
+}
+
+// Every weight used by the evaluation, so that different engine personalities can be tried
+// without recompiling. Material is measured in pawns
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    pub pawn_value:         f64,
+    pub knight_value:       f64,
+    pub bishop_value:       f64,
+    pub rook_value:         f64,
+    pub queen_value:        f64,
+
+    pub centrality:         f64, // Per central pawn
+    pub check_bonus:        f64,
+    pub mobility:           f64, // Per square attacked
+    pub hanging_piece:      f64, // Per enemy piece the side to move can take for free
+
+    pub castle_right:       f64,
+    pub king_attack_knight: f64, // Attack units per king zone square attacked, by attacker
+    pub king_attack_bishop: f64,
+    pub king_attack_rook:   f64,
+    pub king_attack_queen:  f64,
+    pub king_attack_scale:  f64, // Penalty is scale * units^2, up to the cap
+    pub king_attack_cap:    f64,
+    pub pawn_shield_front:  f64,
+    pub pawn_shield_back:   f64,
+    pub open_file:          f64,
+    pub half_open_file:     f64,
+    pub pawn_storm:         f64,
+    pub tropism:            f64,
+
+    pub doubled_pawn:       f64,
+    pub isolated_pawn:      f64,
+    pub backward_pawn:      f64,
+    pub connected_pawn:     f64,
+    pub passed_pawn:        [f64; 8] // Indexed by rank relative to the pawn's own side
+}
+
+// Pawns can never stand on their first or last rank, so only these passed pawn ranks are named
+const PASSED_PAWN_NAMES: [&str; 6] = ["passed_pawn_rank_2", "passed_pawn_rank_3", "passed_pawn_rank_4",
+                                      "passed_pawn_rank_5", "passed_pawn_rank_6", "passed_pawn_rank_7"];
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        EvalParams {
+            pawn_value:         1.0,
+            knight_value:       3.0,
+            bishop_value:       3.0,
+            rook_value:         5.0,
+            queen_value:        9.0,
+
+            centrality:         0.1,
+            check_bonus:        0.5,
+            mobility:           0.1,
+            hanging_piece:      0.2,
+
+            castle_right:       0.1,
+            king_attack_knight: 2.0,
+            king_attack_bishop: 2.0,
+            king_attack_rook:   3.0,
+            king_attack_queen:  5.0,
+            king_attack_scale:  0.01,
+            king_attack_cap:    5.0,
+            pawn_shield_front:  0.1,
+            pawn_shield_back:   0.05,
+            open_file:          0.25,
+            half_open_file:     0.15,
+            pawn_storm:         0.05,
+            tropism:            0.01,
+
+            doubled_pawn:       0.2,
+            isolated_pawn:      0.15,
+            backward_pawn:      0.1,
+            connected_pawn:     0.05,
+            passed_pawn:        [0.0, 0.05, 0.1, 0.2, 0.35, 0.6, 1.0, 0.0]
+        }
+    }
+}
+
+impl EvalParams {
+    pub fn fields(&self) -> Vec<(&'static str, f64)> {
+        let mut params: EvalParams = *self;
+        params.fields_mut().into_iter().map(|(name, value)| (name, *value)).collect()
+    }
+
+    pub fn fields_mut(&mut self) -> Vec<(&'static str, &mut f64)> {
+
+        let mut fields: Vec<(&'static str, &mut f64)> = vec![
+            ("pawn_value", &mut self.pawn_value),
+            ("knight_value", &mut self.knight_value),
+            ("bishop_value", &mut self.bishop_value),
+            ("rook_value", &mut self.rook_value),
+            ("queen_value", &mut self.queen_value),
+            ("centrality", &mut self.centrality),
+            ("check_bonus", &mut self.check_bonus),
+            ("mobility", &mut self.mobility),
+            ("hanging_piece", &mut self.hanging_piece),
+            ("castle_right", &mut self.castle_right),
+            ("king_attack_knight", &mut self.king_attack_knight),
+            ("king_attack_bishop", &mut self.king_attack_bishop),
+            ("king_attack_rook", &mut self.king_attack_rook),
+            ("king_attack_queen", &mut self.king_attack_queen),
+            ("king_attack_scale", &mut self.king_attack_scale),
+            ("king_attack_cap", &mut self.king_attack_cap),
+            ("pawn_shield_front", &mut self.pawn_shield_front),
+            ("pawn_shield_back", &mut self.pawn_shield_back),
+            ("open_file", &mut self.open_file),
+            ("half_open_file", &mut self.half_open_file),
+            ("pawn_storm", &mut self.pawn_storm),
+            ("tropism", &mut self.tropism),
+            ("doubled_pawn", &mut self.doubled_pawn),
+            ("isolated_pawn", &mut self.isolated_pawn),
+            ("backward_pawn", &mut self.backward_pawn),
+            ("connected_pawn", &mut self.connected_pawn)
+        ];
+
+        for (name, value) in PASSED_PAWN_NAMES.iter().zip(self.passed_pawn[1..7].iter_mut()) {
+            fields.push((name, value));
+        }
+
+        fields
+    }
+
+    pub fn set(&mut self, name: &str, value: f64) -> Result<(), Box<dyn Error>> {
+
+        // Names are matched case-insensitively, as UCI option names are
+        match self.fields_mut().into_iter().find(|(field, _)| field.eq_ignore_ascii_case(name)) {
+            Some((_, field)) => {
+                *field = value;
+                Ok(())
+            },
+            None => Err(format!("Unknown evaluation parameter: {}", name).into())
+        }
+    }
+
+    pub fn set_from_str(&mut self, assignment: &str) -> Result<(), Box<dyn Error>> {
+
+        // Parses "name = value", as used in parameter files and the --set flag
+        let (name, value) = assignment.split_once('=').ok_or(format!("Expected name = value, got: {}", assignment))?;
+        let value: f64 = value.trim().parse().map_err(|_| format!("Invalid value for {}: {}", name.trim(), value.trim()))?;
+
+        self.set(name.trim(), value)
+    }
+
+    // Files are a flat subset of TOML - one "name = value" per line, with # comments
+    // Any parameters missing from the file keep their default values
+
+    pub fn load(path: &str) -> Result<EvalParams, Box<dyn Error>> {
+
+        let mut params: EvalParams = EvalParams::default();
+
+        for line in fs::read_to_string(path)?.lines() {
+            let line: &str = line.split('#').next().unwrap_or("").trim();
+            if !line.is_empty() {
+                params.set_from_str(line)?;
+            }
+        }
+
+        Ok(params)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+
+        let mut contents: String = String::from("# Crabablanca evaluation parameters\n");
+
+        for (name, value) in self.fields() {
+            contents.push_str(&format!("{} = {:?}\n", name, value));
+        }
+
+        fs::write(path, contents)?;
+
+        Ok(())
+    }
+}
+
+// One evaluation term, scored from each side's own point of view
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EvalTerm {
+    pub white: f64,
+    pub black: f64
+}
+
+impl EvalTerm {
+    fn new(white: f64, black: f64) -> EvalTerm {
+        EvalTerm { white, black }
+    }
+
+    pub fn total(&self) -> f64 {
+        self.white - self.black
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EvalTrace {
+    pub checkmate:      (bool, bool), // (white checkmated, black checkmated) - other terms are left empty
+    pub material:       EvalTerm,
+    pub centrality:     EvalTerm,
+    pub checks:         EvalTerm,
+    pub mobility:       EvalTerm,
+    pub threats:        EvalTerm,
+    pub king_safety:    EvalTerm,
+    pub pawn_structure: EvalTerm
+}
+
+impl EvalTrace {
+    pub fn terms(&self) -> Vec<(&'static str, EvalTerm)> {
+        vec![
+            ("material", self.material),
+            ("centrality", self.centrality),
+            ("checks", self.checks),
+            ("mobility", self.mobility),
+            ("threats", self.threats),
+            ("king safety", self.king_safety),
+            ("pawn structure", self.pawn_structure)
+        ]
+    }
+
+    pub fn total(&self) -> f64 {
+        if self.checkmate.0 {
+            -9999.0
+        } else if self.checkmate.1 {
+            9999.0
+        } else {
+            self.terms().iter().map(|(_, term)| term.total()).sum()
+        }
+    }
+}
+
+pub fn evaluate(board: &Board, params: &EvalParams) -> f64 {
+    evaluate_trace(board, params).total()
+}
+
+pub fn evaluate_trace(board: &Board, params: &EvalParams) -> EvalTrace {
+
+    // Check checkmates
+
+    let checks = board.check_check();
+    let checkmates = board.check_checkmate(checks);
+
+    if checkmates.0 || checkmates.1 {
+        return EvalTrace {
+            checkmate: checkmates,
+            ..EvalTrace::default()
+        }
+    }
+
+    // Count material balance
+    let white_material: f64 = params.pawn_value * count_bits(board.white_pawns) as f64 +
+                              params.bishop_value * count_bits(board.white_bishops) as f64 +
+                              params.knight_value * count_bits(board.white_knights) as f64 +
+                              params.rook_value * count_bits(board.white_rooks) as f64 +
+                              params.queen_value * count_bits(board.white_queens) as f64;
+
+    let black_material: f64 = params.pawn_value * count_bits(board.black_pawns) as f64 +
+                              params.bishop_value * count_bits(board.black_bishops) as f64 +
+                              params.knight_value * count_bits(board.black_knights) as f64 +
+                              params.rook_value * count_bits(board.black_rooks) as f64 +
+                              params.queen_value * count_bits(board.black_queens) as f64;
+
+    let material: EvalTerm = EvalTerm::new(white_material, black_material);
+
+    // Assess centrality
+    let white_central_pawns: f64 = count_bits(board.white_pawns & CENTRE) as f64;
+    let black_central_pawns: f64 = count_bits(board.black_pawns & CENTRE) as f64;
+
+    let centrality: EvalTerm = EvalTerm::new(params.centrality * white_central_pawns, params.centrality * black_central_pawns);
+
+    // Encourage attacking play
+    let checks: EvalTerm = EvalTerm::new(params.check_bonus * checks.1 as u8 as f64, params.check_bonus * checks.0 as u8 as f64);
+
+    // Encourage development
+    let white_attacks: AttackMap = board.attack_map(1);
+    let black_attacks: AttackMap = board.attack_map(0);
+
+    let mobility: EvalTerm = EvalTerm::new(params.mobility * white_attacks.mobility as f64, params.mobility * black_attacks.mobility as f64);
+
+    // Pieces left attacked and undefended can be taken by the side to move
+    let white_hanging: f64 = count_bits(board.hanging_pieces(1, &white_attacks, &black_attacks)) as f64;
+    let black_hanging: f64 = count_bits(board.hanging_pieces(0, &black_attacks, &white_attacks)) as f64;
+
+    let threats: EvalTerm = if board.to_move == 1 {
+        EvalTerm::new(params.hanging_piece * black_hanging, 0.0)
+    } else {
+        EvalTerm::new(0.0, params.hanging_piece * white_hanging)
+    };
+
+    // Encourage king safety
+    // King safety matters less as material comes off, until the king should become active
+    let phase: f64 = game_phase(board);
+
+    let king_safety: EvalTerm = EvalTerm::new(phase * king_safety(board, 1, &black_attacks, params), phase * king_safety(board, 0, &white_attacks, params));
+
+    // Assess pawn structure
+    let pawn_structure: PawnStructure = PAWN_TABLE.with(|table| table.borrow_mut().probe(board.white_pawns, board.black_pawns));
+
+    let pawn_structure: EvalTerm = EvalTerm::new(pawn_structure_score(&pawn_structure.white, params), pawn_structure_score(&pawn_structure.black, params));
+
+    EvalTrace {
+        checkmate: (false, false),
+        material,
+        centrality,
+        checks,
+        mobility,
+        threats,
+        king_safety,
+        pawn_structure
+    }
+}
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use crate::board::Board;
+
+// Every position of a game so far, along with the time white and black had left on reaching it
+// under a time control, so that moves can be taken back. Positions after the ply reached are
+// ones taken back, which redo plays again until a different move is made
+
+pub struct GameHistory {
+    positions: Vec<(Board, Option<[Duration; 2]>)>,
+    ply:       usize // Index of the current position
+}
+
+impl GameHistory {
+    pub fn new(board: Board, clocks: Option<[Duration; 2]>) -> GameHistory {
+        GameHistory {
+            positions: vec![(board, clocks)],
+            ply:       0
+        }
+    }
+
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    pub fn current(&self) -> (Board, Option<[Duration; 2]>) {
+        self.positions[self.ply]
+    }
+
+    pub fn push(&mut self, board: Board, clocks: Option<[Duration; 2]>) {
+        // A new move replaces any that were taken back
+        self.positions.truncate(self.ply + 1);
+        self.positions.push((board, clocks));
+        self.ply += 1;
+    }
+
+    pub fn undo(&mut self, plies: usize, players: &[u8]) -> Option<(Board, Option<[Duration; 2]>)> {
+        self.travel(self.ply.saturating_sub(plies), players)
+    }
+
+    pub fn redo(&mut self, plies: usize, players: &[u8]) -> Option<(Board, Option<[Duration; 2]>)> {
+        self.travel((self.ply + plies).min(self.positions.len() - 1), players)
+    }
+
+    fn travel(&mut self, target: usize, players: &[u8]) -> Option<(Board, Option<[Duration; 2]>)> {
+
+        // Only to positions where one of the players (the sides not played by the engine) is to
+        // move, as going back to before the engine's first move would only have it move again.
+        // With the engine playing both sides, any position will do
+        if target == self.ply || !(players.is_empty() || players.contains(&self.positions[target].0.to_move)) {
+            return None;
+        }
+
+        self.ply = target;
+        Some(self.current())
+    }
+
+    pub fn move_list(&self) -> Vec<String> {
+        // Moves up to the current position, in standard notation
+        self.positions[..=self.ply].windows(2).map(|pair| pair[0].0.to_san(&pair[1].0)).collect()
+    }
+
+    pub fn last_move(&self) -> Option<(u64, u64)> {
+        // From and to squares of the move that led to the current position
+        self.ply.checked_sub(1).map(|previous| self.positions[previous].0.move_squares(&self.positions[self.ply].0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(board: &Board, input: &str) -> Board {
+        board.parse_input(&input.to_string()).unwrap()
+    }
+
+    // The first plies of e4 e5 Nf3 Nc6
+    fn game(plies: usize) -> GameHistory {
+        let mut history: GameHistory = GameHistory::new(Board::new(), None);
+        for input in ["e4", "e5", "Nf3", "Nc6"].into_iter().take(plies) {
+            let (board, _) = history.current();
+            history.push(play(&board, input), None);
+        }
+        history
+    }
+
+    #[test]
+    fn undoes_a_move_pair_against_the_engine() {
+        let mut history: GameHistory = game(4);
+
+        let (board, _) = history.undo(2, &[1]).unwrap();
+        assert_eq!(history.ply(), 2);
+        assert_eq!(board.to_move, 1);
+        assert_eq!(history.move_list(), vec!["e4", "e5"]);
+        assert_eq!(history.last_move(), Some((1 << 52, 1 << 36)));
+    }
+
+    #[test]
+    fn refuses_to_undo_past_the_engines_first_move() {
+        // Playing black, the start position is the engine's to move
+        let mut history: GameHistory = game(3);
+        assert!(history.undo(2, &[0]).is_some());
+        assert!(history.undo(2, &[0]).is_none());
+        assert_eq!(history.ply(), 1);
+
+        // Nor past the start of the game
+        let mut history: GameHistory = game(4);
+        assert!(history.undo(2, &[1]).is_some());
+        assert!(history.undo(2, &[1]).is_some());
+        assert!(history.undo(2, &[1]).is_none());
+        assert_eq!(history.last_move(), None);
+    }
+
+    #[test]
+    fn redoes_after_undo() {
+        let mut history: GameHistory = game(4);
+        let (end, _) = history.current();
+
+        assert!(history.redo(2, &[1]).is_none());
+        history.undo(2, &[1]).unwrap();
+        history.undo(2, &[1]).unwrap();
+
+        assert_eq!(history.redo(2, &[1]).map(|(board, _)| board.to_move), Some(1));
+        assert_eq!(history.redo(2, &[1]).map(|(board, _)| board), Some(end));
+        assert!(history.redo(2, &[1]).is_none());
+    }
+
+    #[test]
+    fn new_move_replaces_the_moves_taken_back() {
+        let mut history: GameHistory = game(4);
+        history.undo(2, &[1]).unwrap();
+
+        let (board, _) = history.current();
+        history.push(play(&board, "Bc4"), None);
+
+        assert_eq!(history.move_list(), vec!["e4", "e5", "Bc4"]);
+        assert!(history.redo(1, &[1]).is_none());
+    }
+
+    #[test]
+    fn engine_against_itself_goes_back_a_ply_at_a_time() {
+        let mut history: GameHistory = game(4);
+
+        assert!(history.undo(1, &[]).is_some());
+        assert_eq!(history.ply(), 3);
+        assert_eq!(history.current().0.to_move, 0);
+    }
+
+    #[test]
+    fn keeps_the_clocks() {
+        let start: [Duration; 2] = [Duration::from_secs(300); 2];
+        let mut history: GameHistory = GameHistory::new(Board::new(), Some(start));
+        history.push(play(&Board::new(), "d4"), Some([Duration::from_secs(290), Duration::from_secs(300)]));
+
+        assert_eq!(history.undo(1, &[1, 0]).map(|(_, clocks)| clocks), Some(Some(start)));
+    }
+}
@@ -1,5 +1,15 @@
 pub mod constants;
 pub mod bit_functions;
+pub mod attack_tables;
 pub mod board;
+pub mod backend;
 pub mod renderer;
+pub mod theme;
+pub mod diagram;
+pub mod history;
+pub mod zobrist;
+pub mod pawns;
+pub mod evaluation;
+pub mod book;
+pub mod tablebase;
 pub mod engine;
\ No newline at end of file
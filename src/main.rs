@@ -1,45 +1,166 @@
 use core::time;
 use std::error::Error;
 use std::io::{self, Write};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::env;
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 
-use std::rc::{Rc, Weak};
-use std::cell::RefCell;
+use crabablanca::board::Board;
+use crabablanca::renderer::{Renderer, INPUT_ROW, STATUS_ROW};
+use crabablanca::theme::{Theme, THEME_NAMES};
+use crabablanca::diagram::{to_svg, DiagramOptions};
+use crabablanca::history::GameHistory;
+use crabablanca::book::{BookMode, OpeningBook};
+use crabablanca::engine::{format_eval, mate_search, start_search, MateResult, SearchHandle, SearchInfo, SearchOptions, SearchResult, TranspositionTable, MAX_DEPTH};
+use crabablanca::tablebase::{EndgameTablebase, TablebaseMove};
+use crabablanca::evaluation::{evaluate_trace, EvalParams, EvalTrace};
 
-use std::collections::HashMap;
+use crossterm::{execute, cursor, style::Print};
+use crossterm::event::{poll, read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, MouseButton, MouseEventKind};
+use crossterm::terminal::{self, Clear, ClearType, disable_raw_mode, enable_raw_mode};
 
-use crabablanca::board::Board;
-use crabablanca::renderer::Renderer;
-use crabablanca::engine::Node;
+const TT_SIZE_MB: usize = 64;
+
+struct Config {
+    params:    EvalParams,
+    book:      Option<String>,
+    book_mode: BookMode,
+    syzygy:    Option<String>,
+    threads:   usize,
+    movetime:  u64,
+    clock:     Option<(Duration, Duration)>, // Time each side starts with, and the increment per move
+    theme:     Option<String>,               // Chosen from the environment if not given
+    ascii:     bool
+}
+
+fn parse_args() -> Result<Config, Box<dyn Error>> {
+
+    // Flags are applied in order, so --set after --params overrides values from the file
+    let mut config: Config = Config {
+        params:    EvalParams::default(),
+        book:      None,
+        book_mode: BookMode::Weighted,
+        syzygy:    None,
+        threads:   1,
+        movetime:  3000,
+        clock:     None,
+        theme:     None,
+        ascii:     false
+    };
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--params" => {
+                let path: String = args.next().ok_or("--params requires a file")?;
+                config.params = EvalParams::load(&path)?;
+            },
+            "--set" => {
+                let assignment: String = args.next().ok_or("--set requires name=value")?;
+                config.params.set_from_str(&assignment)?;
+            },
+            "--book" => config.book = Some(args.next().ok_or("--book requires a Polyglot .bin file")?),
+            "--syzygy" => config.syzygy = Some(args.next().ok_or("--syzygy requires a directory of Syzygy tables")?),
+            "--threads" => config.threads = args.next().ok_or("--threads requires a number")?.parse()?,
+            "--movetime" => config.movetime = args.next().ok_or("--movetime requires a time in milliseconds")?.parse()?,
+            "--clock" => config.clock = Some(parse_clock(&args.next().ok_or("--clock requires a time control, eg. 5+3")?)?),
+            "--theme" => {
+                let name: String = args.next().ok_or("--theme requires a theme name")?;
+                if Theme::named(&name, false).is_none() {
+                    return Err(format!("Unknown theme {} - themes are {}", name, THEME_NAMES.join(", ")).into());
+                }
+                config.theme = Some(name);
+            },
+            "--ascii" => config.ascii = true,
+            "--book-mode" => {
+                config.book_mode = match args.next().as_deref() {
+                    Some("best") => BookMode::Best,
+                    Some("weighted") => BookMode::Weighted,
+                    _ => return Err("--book-mode must be best or weighted".into())
+                };
+            },
+            _ => return Err(format!("Unknown argument: {}", arg).into())
+        }
+    }
+
+    Ok(config)
+}
+
+fn parse_clock(time_control: &str) -> Result<(Duration, Duration), Box<dyn Error>> {
 
-use crossterm::{execute, cursor};
-use crossterm::event::{read, Event, KeyCode};
-use crossterm::terminal::{Clear, ClearType, disable_raw_mode, enable_raw_mode};
+    // Minutes for each side, with an optional increment in seconds per move, eg. "5" or "5+3"
+    let (minutes, increment) = time_control.split_once('+').unwrap_or((time_control, "0"));
+
+    Ok((Duration::try_from_secs_f64(minutes.parse::<f64>()? * 60.0)?, Duration::try_from_secs_f64(increment.parse()?)?))
+}
 
 fn main() -> Result<(), Box<dyn Error>>{
 
     env::set_var("RUST_BACKTRACE", "full");
 
+    let config: Config = parse_args()?;
+    let mut params: EvalParams = config.params;
+
+    let mut book: Option<OpeningBook> = match &config.book {
+        Some(path) => Some(OpeningBook::load(path, config.book_mode)?),
+        None => None
+    };
+    let mut use_book: bool = book.is_some();
+
+    let tablebase: Option<Arc<EndgameTablebase>> = match &config.syzygy {
+        Some(path) => Some(Arc::new(EndgameTablebase::new(path)?)),
+        None => None
+    };
+
+    let mut threads: usize = config.threads.max(1);
+    let mut movetime: u64 = config.movetime;
+    let mut multi_pv: usize = 1;
+
+    // With a time control, the time each side has left, indexed by to_move like the sides
+    // themselves. Time only comes off a clock once its side has moved
+    let mut clocks: Option<[Duration; 2]> = config.clock.map(|(time, _)| [time, time]);
+    let increment: Duration = config.clock.map(|(_, increment)| increment).unwrap_or(Duration::ZERO);
+
+    // Moves of the game so far, in standard notation
+    let mut move_list: Vec<String> = vec![];
+
+    // Shared by the search threads, and kept between moves so that each search starts with
+    // what the previous ones learned
+    let tt: Arc<TranspositionTable> = Arc::new(TranspositionTable::new(TT_SIZE_MB));
+
     let mut board: Board = Board::new(); 
 
-    let mut renderer = Renderer::new()?;
+    // Every position of the game so far, so that moves can be taken back
+    let mut history: GameHistory = GameHistory::new(board, clocks);
+
+    let theme: Theme = match &config.theme {
+        Some(name) => Theme::named(name, config.ascii).ok_or("Unknown theme")?,
+        None => Theme::from_env(config.ascii)
+    };
+
+    let mut renderer = Renderer::new(theme)?;
+    renderer.set_clocks(clocks.map(|clocks| running_clocks(clocks, 1, Duration::ZERO)));
     
     // [1] for white, [0] for black, [] for engine vs. engine, [1, 0] for self vs. self
     let mut player_colour: Vec<u8> = vec![1, 0]; 
-    let depth: usize = 4;
     let mut showme = false;
 
-    // TODO: en passant, transposition tables, multithreading
-    // Performance improvements: block_ray, knight_move_mask, move_piece (maybe?)
+    // TODO: en passant
+    // Performance improvements: move_piece (maybe?)
 
     enable_raw_mode()?;
+    execute!(io::stdout(), EnableMouseCapture)?;
 
     for _ in 0..200 {
         // std::thread::sleep(time::Duration::from_secs(1));
 
-        let search_node = Rc::new(RefCell::new(Node::new(&board)));
-        
+        // The position before this turn, to show the move made from it afterwards
+        let position: Board = board;
+
         renderer.parse_board(&board)?;
 
         if showme {
@@ -53,21 +174,6 @@ fn main() -> Result<(), Box<dyn Error>>{
 
         renderer.parse_board(&board)?;
 
-        // Node::search_n_plys(&search_node, depth);
-        Node::process_node_cell(&search_node, depth);
-        execute!(
-            io::stdout(),
-            cursor::MoveToColumn(0),
-            Clear(ClearType::CurrentLine)
-        )?;
-        println!("{}, {}, {}, {}", search_node.borrow().children.len(), search_node.borrow().static_eval, search_node.borrow().deep_eval, depth);
-                
-        execute!(
-            io::stdout(),
-            cursor::MoveToColumn(0),
-            Clear(ClearType::CurrentLine)
-        )?;
-
         if board.white_checkmate {
             println!("Checkmate - black wins");
             execute!(
@@ -75,6 +181,7 @@ fn main() -> Result<(), Box<dyn Error>>{
                 cursor::MoveToColumn(0),
                 Clear(ClearType::CurrentLine)
             )?;
+            execute!(io::stdout(), DisableMouseCapture)?;
             disable_raw_mode()?;
             break;
         } else if board.black_checkmate {
@@ -84,33 +191,200 @@ fn main() -> Result<(), Box<dyn Error>>{
                 cursor::MoveToColumn(0),
                 Clear(ClearType::CurrentLine)
             )?;
+            execute!(io::stdout(), DisableMouseCapture)?;
             disable_raw_mode()?;
             break;
         }
 
-        if player_colour.contains(&board.to_move){
-
-            let mut input = String::new();
-            // Capture input
-            loop {
-                if let Event::Key(key_event) = read()? {
-                    match key_event.code {
-                        KeyCode::Enter => break,
-                        KeyCode::Backspace => {
-                            input.pop();
-                            print!("\x08 \x08");
-                            io::stdout().flush().unwrap();
-                        }
-                        KeyCode::Char(c) => {
-                            input.push(c);
-                            print!("{}", c);
-                            io::stdout().flush().unwrap();
+        let turn_start: Instant = Instant::now();
+
+        // With a time control the engine budgets its time from what's left on its clock
+        let think_time: Duration = match clocks {
+            Some(clocks) => {
+                let left: Duration = clocks[board.to_move as usize];
+                (left / 30 + increment / 2).min(left / 2)
+            },
+            None => Duration::from_millis(movetime)
+        };
+
+        // Play from the opening book while in it, and only search once out of book
+        let book_move: Option<Board> = match book.as_mut() {
+            Some(b) if use_book => b.pick_move(&board),
+            _ => None
+        };
+
+        // Likewise play tablebase-optimal moves in endgames the tablebases cover
+        let tablebase_move: Option<TablebaseMove> = tablebase.as_ref().and_then(|tb| tb.best_move(&board));
+
+        let mut best_move: Option<Board> = None;
+        let mut search_handle: Option<SearchHandle> = None;
+
+        if let Some(next_move) = book_move {
+            print_status("book move")?;
+            best_move = Some(next_move);
+        } else if let Some(tb_move) = tablebase_move {
+            let result: &str = match tb_move.wdl {
+                2 | 1 => "win",
+                0 => "draw",
+                _ => "loss"
+            };
+            print_status(&format!("tablebase move ({}, dtz {})", result, tb_move.dtz))?;
+            best_move = Some(tb_move.board);
+        } else {
+            // Search runs in the background, so that commands can be typed (or the search
+            // stopped) while it thinks
+            let options: SearchOptions = SearchOptions {
+                depth:    MAX_DEPTH,
+                time:     Some(think_time),
+                nodes:    None,
+                threads,
+                multi_pv
+            };
+            search_handle = Some(start_search(board, params, tablebase.clone(), Arc::clone(&tt), options));
+        }
+
+        let players_turn: bool = player_colour.contains(&board.to_move);
+        let mut input = String::new();
+
+        // Moves can also be made by picking up a piece and putting it down with the mouse or
+        // the arrow keys and space, rather than typing them
+        let mut selected: Option<u64> = None;
+        let mut chosen_move: Option<Board> = None;
+
+        let mut out_of_time: bool = false;
+
+        // Position gone back or forward to, with undo or redo
+        let mut travel: Option<(Board, Option<[Duration; 2]>)> = None;
+        let mut clock_shown: u64 = 0;
+
+        execute!(
+            io::stdout(),
+            cursor::MoveTo(0, INPUT_ROW),
+            Clear(ClearType::CurrentLine)
+        )?;
+
+        // Capture input, showing the search's progress meanwhile. On the engine's turn this
+        // only waits for the search to finish, which typing stop cuts short
+        loop {
+            if let Some(handle) = search_handle.as_ref() {
+                show_progress(handle, &board)?;
+                if handle.is_finished() {
+                    best_move = finish_search(search_handle.take().unwrap(), &mut renderer, &board, threads)?.best_move;
+                }
+            }
+
+            // Keep the running clock up to date, redrawing it once a second
+            if let Some(clocks) = clocks {
+                let elapsed: Duration = turn_start.elapsed();
+                if elapsed.as_secs() != clock_shown {
+                    clock_shown = elapsed.as_secs();
+                    renderer.set_clocks(Some(running_clocks(clocks, board.to_move, elapsed)));
+                    renderer.draw_panel(&board)?;
+                }
+                if elapsed >= clocks[board.to_move as usize] {
+                    out_of_time = true;
+                    break;
+                }
+            }
+
+            if !players_turn && search_handle.is_none() {
+                break;
+            }
+
+            if !poll(Duration::from_millis(50))? {
+                continue;
+            }
+
+            let square: Option<u64> = match read()? {
+                Event::Mouse(mouse_event) if mouse_event.kind == MouseEventKind::Down(MouseButton::Left) => {
+                    renderer.square_at(mouse_event.column, mouse_event.row)
+                },
+                Event::Resize(columns, _) => {
+                    renderer.resize(columns)?;
+                    renderer.parse_board(&board)?;
+                    execute!(io::stdout(), cursor::MoveTo(0, INPUT_ROW), Print(&input))?;
+                    None
+                },
+                Event::Key(key_event) => match key_event.code {
+                    KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
+                        let (right, down) = match key_event.code {
+                            KeyCode::Up => (0, -1),
+                            KeyCode::Down => (0, 1),
+                            KeyCode::Left => (-1, 0),
+                            _ => (1, 0)
+                        };
+                        renderer.move_cursor(right, down);
+                        renderer.parse_board(&board)?;
+                        None
+                    },
+                    // Space only picks a square when nothing has been typed, so that commands can still contain spaces
+                    KeyCode::Char(' ') if input.is_empty() && renderer.cursor().is_some() => renderer.cursor(),
+                    KeyCode::Enter => {
+                        if input.trim() == "stop" {
+                            if let Some(handle) = search_handle.as_ref() {
+                                handle.stop();
+                            }
+                        } else if players_turn {
+                            break;
                         }
-                        _ => {}
+                        input.clear();
+                        execute!(
+                            io::stdout(),
+                            cursor::MoveToColumn(0),
+                            Clear(ClearType::CurrentLine)
+                        )?;
+                        None
+                    },
+                    KeyCode::Backspace => {
+                        input.pop();
+                        print!("\x08 \x08");
+                        io::stdout().flush().unwrap();
+                        None
                     }
+                    KeyCode::Char(c) => {
+                        input.push(c);
+                        print!("{}", c);
+                        io::stdout().flush().unwrap();
+                        None
+                    }
+                    _ => None
+                },
+                _ => None
+            };
+
+            if let Some(square) = square.filter(|_| players_turn) {
+                chosen_move = select_square(&mut renderer, &board, &mut selected, square)?;
+                if chosen_move.is_some() {
+                    input.clear();
+                    break;
                 }
             }
 
+            // Redrawing the board leaves the cursor in the status line
+            execute!(io::stdout(), cursor::MoveTo(input.chars().count() as u16, INPUT_ROW))?;
+        }
+
+        // Only next and preview need the search's move, so anything else stops it early
+        if let Some(handle) = search_handle.take() {
+            if !matches!(input.trim(), "next" | "preview") {
+                handle.stop();
+            }
+            best_move = finish_search(handle, &mut renderer, &board, threads)?.best_move;
+        }
+
+        if out_of_time {
+            println!("{} loses on time", if board.to_move == 1 {"White"} else {"Black"});
+            execute!(
+                io::stdout(),
+                cursor::MoveToColumn(0),
+                Clear(ClearType::CurrentLine)
+            )?;
+            execute!(io::stdout(), DisableMouseCapture)?;
+            disable_raw_mode()?;
+            break;
+        }
+
+        if players_turn {
 
             execute!(
                 io::stdout(),
@@ -120,60 +394,424 @@ fn main() -> Result<(), Box<dyn Error>>{
 
             // Any new non-move commands go into this match statement
 
-            match input.as_str() {
-                "exit" => {
-                    disable_raw_mode()?;
-                    println!();
-                    return Ok(())
+            let words: Vec<&str> = input.split_whitespace().collect();
+
+            match words.as_slice() {
+                [] => {
+                    match chosen_move {
+                        Some(next_move) => board = next_move,
+                        None => print_message("Invalid or ambiguous command")?
+                    }
                 },
-                "quit" => {
+                ["exit"] | ["quit"] => {
+                    execute!(io::stdout(), DisableMouseCapture)?;
                     disable_raw_mode()?;
                     println!();
                     return Ok(())
                 },
-                "next" => {
-                    if let Some(next_move) = search_node.borrow().best_next_move.clone() {
+                ["next"] => {
+                    if let Some(next_move) = best_move {
                         board = next_move;
                     };
                 },
-                "preview" => {
-                    if let Some(next_move) = search_node.borrow().best_next_move.clone() {
+                ["preview"] => {
+                    if let Some(next_move) = best_move {
                         renderer.parse_board(&next_move)?;
                         std::thread::sleep(time::Duration::from_secs(3));
                         renderer.parse_board(&board)?;
                     };
                 },
-                "play" => player_colour = vec![],
-                "white" => player_colour = vec![1],
-                "black" => player_colour = vec![2],
-                "showme" => showme = true,
-                "!showme" => showme = false,
+                ["eval"] => {
+                    print_eval_trace(&board, &params)?;
+                    // Leave the breakdown up until a key is pressed
+                    read()?;
+                    execute!(
+                        io::stdout(),
+                        cursor::MoveTo(0, STATUS_ROW),
+                        Clear(ClearType::FromCursorDown)
+                    )?;
+                },
+                ["analyse"] | ["analyse", _] => {
+                    let lines: Result<usize, _> = match words.get(1) {
+                        Some(n) => n.parse::<usize>(),
+                        None => Ok(multi_pv)
+                    };
+                    match lines {
+                        Ok(n) if n > 0 => {
+                            let options: SearchOptions = SearchOptions {
+                                depth:    MAX_DEPTH,
+                                time:     Some(Duration::from_millis(movetime)),
+                                nodes:    None,
+                                threads,
+                                multi_pv: n
+                            };
+                            analyse(&board, params, tablebase.clone(), Arc::clone(&tt), options)?;
+                        },
+                        _ => print_message("analyse takes a number of lines to show")?
+                    }
+                },
+                ["mate", n] => {
+                    match n.parse::<usize>() {
+                        Ok(moves) if moves > 0 => find_mate(&board, moves)?,
+                        _ => print_message("mate takes a number of moves to search")?
+                    }
+                },
+                ["moves", piece] => {
+                    match board.find_piece(piece) {
+                        Some(square) => {
+                            let destinations: u64 = board.destinations(square);
+                            if destinations == 0 {
+                                print_message(&format!("{} has no legal moves", piece))?;
+                            }
+                            renderer.set_destinations(destinations);
+                        },
+                        None => print_message(&format!("No piece to move at {}", piece))?
+                    }
+                },
+                ["play"] => player_colour = vec![],
+                ["white"] => {
+                    player_colour = vec![1];
+                    renderer.set_flipped(false);
+                },
+                ["black"] => {
+                    // Black plays up the board from their side
+                    player_colour = vec![0];
+                    renderer.set_flipped(true);
+                },
+                ["undo"] | ["takeback"] | ["redo"] => {
+                    // Against the engine, a move pair at a time, so it's the player's turn again
+                    // rather than the engine's
+                    let plies: usize = if player_colour.len() == 1 {2} else {1};
+                    travel = if words[0] == "redo" {
+                        history.redo(plies, &player_colour)
+                    } else {
+                        history.undo(plies, &player_colour)
+                    };
+
+                    if travel.is_none() {
+                        print_message(if words[0] == "redo" {"No moves to redo"} else {"No moves to undo"})?;
+                    }
+                },
+                ["flip"] => renderer.flip(),
+                ["theme", "ascii"] => renderer.set_theme(Theme {ascii: true, ..renderer.theme()}),
+                ["theme", "unicode"] => renderer.set_theme(Theme {ascii: false, ..renderer.theme()}),
+                ["theme", name] => {
+                    match Theme::named(name, renderer.theme().ascii) {
+                        Some(theme) => renderer.set_theme(theme),
+                        None => print_message(&format!("Themes are {}, or ascii or unicode for the pieces", THEME_NAMES.join(", ")))?
+                    }
+                },
+                ["diagram", path, arrows @ ..] => {
+                    // Any further words are arrows to draw, eg. "diagram game.svg e2e4 g1f3"
+                    match arrows.iter().map(|arrow| Board::parse_squares(arrow)).collect::<Option<Vec<(u64, u64)>>>() {
+                        Some(arrows) => {
+                            let options: DiagramOptions = DiagramOptions {
+                                flipped:   renderer.is_flipped(),
+                                last_move: renderer.last_move(),
+                                arrows,
+                                ..DiagramOptions::default()
+                            };
+                            match fs::write(path, to_svg(&board, &options)) {
+                                Ok(()) => print_message(&format!("Saved diagram to {}", path))?,
+                                Err(e) => print_message(&format!("Could not save {}: {}", path, e))?
+                            }
+                        },
+                        None => print_message("Arrows are given as from and to squares, eg. e2e4")?
+                    }
+                },
+                ["showme"] => showme = true,
+                ["!showme"] => showme = false,
+                ["setoption", "name", name, "value", value] if name.eq_ignore_ascii_case("threads") => {
+                    match value.parse::<usize>() {
+                        Ok(n) if n > 0 => threads = n,
+                        _ => print_message(&format!("Invalid value for {}: {}", name, value))?
+                    }
+                },
+                ["setoption", "name", name, "value", value] if name.eq_ignore_ascii_case("multipv") => {
+                    match value.parse::<usize>() {
+                        Ok(n) if n > 0 => multi_pv = n,
+                        _ => print_message(&format!("Invalid value for {}: {}", name, value))?
+                    }
+                },
+                ["setoption", "name", name, "value", value] if name.eq_ignore_ascii_case("movetime") => {
+                    match value.parse::<u64>() {
+                        Ok(ms) => movetime = ms,
+                        _ => print_message(&format!("Invalid value for {}: {}", name, value))?
+                    }
+                },
+                ["setoption", "name", name, "value", value] => {
+                    match value.parse::<f64>() {
+                        Ok(value) => {
+                            if let Err(e) = params.set(name, value) {
+                                print_message(&e.to_string())?;
+                            }
+                        },
+                        Err(_) => print_message(&format!("Invalid value for {}: {}", name, value))?
+                    }
+                },
+                ["book", "on"] => {
+                    if book.is_some() {
+                        use_book = true;
+                    } else {
+                        print_message("No opening book loaded - start with --book <file>")?;
+                    }
+                },
+                ["book", "off"] => use_book = false,
+                ["params", "load", path] => {
+                    match EvalParams::load(path) {
+                        Ok(loaded) => params = loaded,
+                        Err(e) => print_message(&format!("Could not load {}: {}", path, e))?
+                    }
+                },
+                ["params", "save", path] => {
+                    if let Err(e) = params.save(path) {
+                        print_message(&format!("Could not save {}: {}", path, e))?;
+                    }
+                },
                 _ => {
                     let boardop: Option<Board> = board.parse_input(&input);
                     match boardop {
                         Some(b) => board = b,
-                        None => {
-                            println!("Invalid or ambiguous command");
-                            execute!(
-                                io::stdout(),
-                                cursor::MoveToColumn(0),
-                                Clear(ClearType::CurrentLine)
-                            )?;
-                            std::thread::sleep(time::Duration::from_secs(1));
-                        }
+                        None => print_message("Invalid or ambiguous command")?
                     }
                 }
             }
 
             input.clear();    
         } else {
-            if let Some(next_move) = search_node.borrow().best_next_move.clone() {
+            if let Some(next_move) = best_move {
                 board = next_move;
             };
         }
 
+        if let Some(position) = travel {
+            // Everything is put back as it was on reaching the position, clocks included. The
+            // engine's evaluation was of the position left behind, so it isn't shown until the
+            // next search
+            (board, clocks) = position;
+
+            move_list = history.move_list();
+            renderer.set_move_list(&move_list);
+            renderer.set_last_move(history.last_move());
+            renderer.set_destinations(0);
+            renderer.set_eval(None);
+            renderer.set_clocks(clocks.map(|clocks| running_clocks(clocks, board.to_move, Duration::ZERO)));
+        } else if board != position {
+            // Once a move is made, it replaces whatever was highlighted in the last position
+            renderer.set_last_move(Some(position.move_squares(&board)));
+            renderer.set_destinations(0);
+
+            move_list.push(position.to_san(&board));
+            renderer.set_move_list(&move_list);
+
+            if let Some(clocks) = clocks.as_mut() {
+                let side: usize = position.to_move as usize;
+                clocks[side] = clocks[side].saturating_sub(turn_start.elapsed()) + increment;
+                renderer.set_clocks(Some(running_clocks(*clocks, board.to_move, Duration::ZERO)));
+            }
+
+            history.push(board, clocks);
+        }
+
     }
+    execute!(io::stdout(), DisableMouseCapture)?;
     disable_raw_mode()?;
     Ok(())
 }
 
+
+fn select_square(renderer: &mut Renderer, board: &Board, selected: &mut Option<u64>, square: u64) -> Result<Option<Board>, Box<dyn Error>> {
+
+    // Picking one of the side to move's pieces selects it and shows where it can go, and then
+    // picking one of those squares returns the move there. Picking anything else puts it down
+    let own_pieces: u64 = if board.to_move == 1 {board.all_white} else {board.all_black};
+    let mut next_move: Option<Board> = None;
+
+    match *selected {
+        Some(from) if square & own_pieces == 0 => {
+            next_move = board.generate_move_list().into_iter().find(|next| board.move_squares(next) == (from, square));
+            if next_move.is_none() {
+                print_status("Illegal move")?;
+            }
+            *selected = None;
+        },
+        Some(from) if from == square => *selected = None,
+        _ if square & own_pieces != 0 => *selected = Some(square),
+        _ => *selected = None
+    }
+
+    match *selected {
+        Some(from) => renderer.set_destinations(board.destinations(from) | from),
+        None => renderer.set_destinations(0)
+    }
+    if next_move.is_none() {
+        renderer.parse_board(board)?;
+    }
+
+    Ok(next_move)
+}
+
+fn running_clocks(clocks: [Duration; 2], to_move: u8, elapsed: Duration) -> (Duration, Duration) {
+    // White's and black's time left, with the time taken so far this turn off the side to move's
+    let mut clocks: [Duration; 2] = clocks;
+    clocks[to_move as usize] = clocks[to_move as usize].saturating_sub(elapsed);
+    (clocks[1], clocks[0])
+}
+
+fn print_message(message: &str) -> Result<(), Box<dyn Error>> {
+
+    // Shows a one line message in the status area long enough to be read
+    println!("{}", message);
+    execute!(
+        io::stdout(),
+        cursor::MoveToColumn(0),
+        Clear(ClearType::CurrentLine)
+    )?;
+    std::thread::sleep(time::Duration::from_secs(1));
+
+    Ok(())
+}
+
+fn print_status(status: &str) -> Result<(), Box<dyn Error>> {
+
+    // The status line sits between the board and the input line, so the cursor is put back
+    // wherever it was for typing to carry on. Long lines are cut short rather than wrapping
+    // onto the input line
+    execute!(
+        io::stdout(),
+        cursor::SavePosition,
+        cursor::MoveTo(0, STATUS_ROW),
+        Clear(ClearType::CurrentLine),
+        Print(fit_to_width(status)),
+        cursor::RestorePosition
+    )?;
+
+    Ok(())
+}
+
+fn fit_to_width(line: &str) -> String {
+    let width: usize = terminal::size().map(|(columns, _)| columns as usize).unwrap_or(80);
+    line.chars().take(width.saturating_sub(1)).collect()
+}
+
+fn print_lines(lines: &[String]) -> Result<(), Box<dyn Error>> {
+
+    // Raw mode doesn't return the cursor to the start of the line, so each line is positioned explicitly
+    for line in lines {
+        execute!(
+            io::stdout(),
+            cursor::MoveToColumn(0),
+            Clear(ClearType::CurrentLine)
+        )?;
+        println!("{}", fit_to_width(line));
+    }
+
+    execute!(io::stdout(), cursor::MoveToColumn(0))?;
+
+    Ok(())
+}
+
+fn analyse(board: &Board, params: EvalParams, tablebase: Option<Arc<EndgameTablebase>>, tt: Arc<TranspositionTable>, options: SearchOptions) -> Result<(), Box<dyn Error>> {
+
+    // Searches for the best few moves, showing progress until done (or until a key is pressed),
+    // then lists each move's line until another key is pressed
+    let handle: SearchHandle = start_search(*board, params, tablebase, tt, options);
+
+    while !handle.is_finished() {
+        show_progress(&handle, board)?;
+        if poll(Duration::from_millis(50))? {
+            read()?;
+            handle.stop();
+        }
+    }
+
+    let result: SearchResult = handle.wait();
+    let lines: Vec<String> = result.lines.iter().enumerate().map(|(i, line)| {
+        format!("{}. {} d{}: {}", i + 1, format_eval(line.eval), result.depth, board.line_to_san(&line.pv))
+    }).collect();
+
+    print_status(&format!("{} lines ({} nodes in {:.2}s) - press any key", lines.len(), result.nodes, result.elapsed.as_secs_f64()))?;
+    print_lines(&lines)?;
+
+    read()?;
+    execute!(
+        io::stdout(),
+        cursor::MoveTo(0, STATUS_ROW),
+        Clear(ClearType::FromCursorDown)
+    )?;
+
+    Ok(())
+}
+
+fn find_mate(board: &Board, moves: usize) -> Result<(), Box<dyn Error>> {
+
+    // Looks for a forced mate in the background until it is found, ruled out, or a key is pressed
+    let stop: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let thread_stop: Arc<AtomicBool> = Arc::clone(&stop);
+    let position: Board = *board;
+    let thread = thread::spawn(move || mate_search(&position, moves, &thread_stop));
+
+    print_status(&format!("looking for mate in {} - press any key to stop", moves))?;
+    while !thread.is_finished() {
+        if poll(Duration::from_millis(50))? {
+            read()?;
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let result: MateResult = thread.join().expect("Mate search thread panicked");
+    let found: String = match &result.line {
+        Some(line) => format!("mate in {}: {}", line.len().div_ceil(2), board.line_to_san(line)),
+        None if result.moves == moves => format!("no mate in {} or fewer", moves),
+        None => format!("stopped - no mate in {} or fewer", result.moves)
+    };
+    print_status(&format!("{} ({} nodes in {:.2}s)", found, result.nodes, result.elapsed.as_secs_f64()))?;
+
+    Ok(())
+}
+
+fn show_progress(handle: &SearchHandle, board: &Board) -> Result<(), Box<dyn Error>> {
+
+    // Only the latest report is worth showing
+    let mut latest: Option<SearchInfo> = None;
+    while let Some(info) = handle.progress() {
+        latest = Some(info);
+    }
+
+    if let Some(info) = latest {
+        print_status(&format!("thinking {:.1}s, {} nodes, {} nps - {} d{}: {}",
+                              info.elapsed.as_secs_f64(), info.nodes, info.nps(), format_eval(info.eval), info.depth, board.line_to_san(&info.pv)))?;
+    }
+
+    Ok(())
+}
+
+fn finish_search(handle: SearchHandle, renderer: &mut Renderer, board: &Board, threads: usize) -> Result<SearchResult, Box<dyn Error>> {
+
+    let result: SearchResult = handle.wait();
+    renderer.set_eval(Some(result.eval));
+    renderer.draw_panel(board)?;
+
+    print_status(&format!("{} d{}: {} ({} nodes in {:.2}s, {} threads)",
+                          format_eval(result.eval), result.depth, board.line_to_san(&result.pv), result.nodes, result.elapsed.as_secs_f64(), threads))?;
+
+    Ok(result)
+}
+
+fn print_eval_trace(board: &Board, params: &EvalParams) -> Result<(), Box<dyn Error>> {
+
+    let trace: EvalTrace = evaluate_trace(board, params);
+
+    let mut lines: Vec<String> = vec![format!("{:<16}{:>8}{:>8}{:>8}", "term", "white", "black", "total")];
+
+    if trace.checkmate.0 || trace.checkmate.1 {
+        lines.push(format!("{:<16}{:>24}", "checkmate", if trace.checkmate.0 {"black wins"} else {"white wins"}));
+    } else {
+        for (name, term) in trace.terms() {
+            lines.push(format!("{:<16}{:>8.2}{:>8.2}{:>8.2}", name, term.white, term.black, term.total()));
+        }
+    }
+    lines.push(format!("{:<16}{:>24.2}", "total", trace.total()));
+
+    print_lines(&lines)
+}
@@ -0,0 +1,218 @@
+use crate::bit_functions::{bidirectional_shift, count_bits, fill_down, fill_up, get_bit_rf, iterate_over, pawn_attacks, shift_left, shift_right};
+use crate::constants::*;
+
+// Pawn structure only changes when a pawn moves or is captured, so it is analysed once per
+// structure and cached in a hash table keyed by the pawn bitboards of both sides
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PawnFeatures {
+    pub doubled:   u8,
+    pub isolated:  u8,
+    pub backward:  u8,
+    pub connected: u8,
+    pub passed:    [u8; 8] // Passed pawn count indexed by rank relative to the pawn's own side
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PawnStructure {
+    pub white:        PawnFeatures,
+    pub black:        PawnFeatures,
+    pub white_passed: u64,
+    pub black_passed: u64
+}
+
+impl PawnStructure {
+    pub fn new(white_pawns: u64, black_pawns: u64) -> PawnStructure {
+
+        let (white, white_passed) = analyse_side(white_pawns, black_pawns, 1);
+        let (black, black_passed) = analyse_side(black_pawns, white_pawns, 0);
+
+        PawnStructure {
+            white,
+            black,
+            white_passed,
+            black_passed
+        }
+    }
+}
+
+fn forward_fill(bits: u64, to_move: u8) -> u64 {
+    if to_move == 1 {fill_up(bits)} else {fill_down(bits)}
+}
+
+fn adjacent(bits: u64) -> u64 {
+    shift_left(bits) | shift_right(bits)
+}
+
+fn analyse_side(own_pawns: u64, enemy_pawns: u64, to_move: u8) -> (PawnFeatures, u64) {
+
+    let mut features: PawnFeatures = PawnFeatures::default();
+
+    // Doubled pawns - every pawn beyond the first on a file counts once
+    for i in 0..8 {
+        let on_file: u8 = count_bits((FILE_A << i) & own_pawns);
+        if on_file > 1 {
+            features.doubled += on_file - 1;
+        }
+    }
+
+    // Isolated pawns have no friendly pawns on either adjacent file
+    let own_files: u64 = fill_up(own_pawns) | fill_down(own_pawns);
+    let isolated: u64 = own_pawns & !adjacent(own_files);
+    features.isolated = count_bits(isolated);
+
+    // Backward pawns have no friendly pawn level with or behind them on an adjacent file
+    // to support their advance, and their stop square is covered by an enemy pawn
+    let supportable: u64 = forward_fill(adjacent(own_pawns), to_move);
+    let stop_attacked: u64 = bidirectional_shift(pawn_attacks(enemy_pawns, to_move ^ 1), 8, to_move ^ 1);
+    let backward: u64 = own_pawns & !supportable & !isolated & stop_attacked;
+    features.backward = count_bits(backward);
+
+    // Connected pawns are either defended by another pawn or stand side by side with one
+    let connected: u64 = own_pawns & (pawn_attacks(own_pawns, to_move) | adjacent(own_pawns));
+    features.connected = count_bits(connected);
+
+    // Passed pawns have no enemy pawns ahead of them on the same or adjacent files, and
+    // are not stuck behind a friendly pawn on the same file
+    let enemy_front: u64 = forward_fill(bidirectional_shift(enemy_pawns, 8, to_move ^ 1), to_move ^ 1);
+    let own_front: u64 = forward_fill(bidirectional_shift(own_pawns, 8, to_move ^ 1), to_move ^ 1);
+    let passed: u64 = own_pawns & !(enemy_front | adjacent(enemy_front)) & !own_front;
+
+    for pawn in iterate_over(passed) {
+        let rank: u8 = get_bit_rf(pawn).0;
+        let relative_rank: u8 = if to_move == 1 {rank} else {7 - rank};
+        features.passed[relative_rank as usize] += 1;
+    }
+
+    (features, passed)
+}
+
+#[derive(Copy, Clone)]
+struct PawnHashEntry {
+    white_pawns: u64,
+    black_pawns: u64,
+    structure:   PawnStructure
+}
+
+pub struct PawnHashTable {
+    entries: Vec<Option<PawnHashEntry>>,
+    pub hits:   u64,
+    pub misses: u64
+}
+
+impl PawnHashTable {
+    pub fn new(size: usize) -> PawnHashTable {
+        // Size is rounded up to a power of two so that indexing is a simple mask
+        PawnHashTable {
+            entries: vec![None; size.max(1).next_power_of_two()],
+            hits:    0,
+            misses:  0
+        }
+    }
+
+    fn index(&self, white_pawns: u64, black_pawns: u64) -> usize {
+        let hash: u64 = white_pawns.wrapping_mul(0x9E3779B97F4A7C15) ^ black_pawns.rotate_left(32).wrapping_mul(0xC2B2AE3D27D4EB4F);
+        ((hash ^ (hash >> 29)) as usize) & (self.entries.len() - 1)
+    }
+
+    pub fn probe(&mut self, white_pawns: u64, black_pawns: u64) -> PawnStructure {
+
+        let index: usize = self.index(white_pawns, black_pawns);
+
+        if let Some(entry) = self.entries[index] {
+            if entry.white_pawns == white_pawns && entry.black_pawns == black_pawns {
+                self.hits += 1;
+                return entry.structure;
+            }
+        }
+
+        // Miss (or collision) - analyse and replace whatever was stored here
+        self.misses += 1;
+        let structure: PawnStructure = PawnStructure::new(white_pawns, black_pawns);
+        self.entries[index] = Some(PawnHashEntry {
+            white_pawns,
+            black_pawns,
+            structure
+        });
+
+        structure
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_functions::get_rank_or_file;
+
+    // Bitboard of the squares named, eg. "a2 c3"
+    fn squares(names: &str) -> u64 {
+        names.split_whitespace().fold(0, |bits, name| {
+            let chars: Vec<char> = name.chars().collect();
+            bits | (get_rank_or_file(chars[0]) & get_rank_or_file(chars[1]))
+        })
+    }
+
+    // White has doubled c-pawns and a passed e-pawn, all isolated. Black's pawns are side by
+    // side on the queenside and defend each other on the kingside
+    fn pawns() -> (u64, u64) {
+        (squares("a2 c3 c4 e5 h2"), squares("a7 b7 g6 h7"))
+    }
+
+    #[test]
+    fn counts_features_of_each_side() {
+        let (white_pawns, black_pawns) = pawns();
+        let structure: PawnStructure = PawnStructure::new(white_pawns, black_pawns);
+
+        assert_eq!(structure.white, PawnFeatures {
+            doubled:   1,
+            isolated:  5,
+            backward:  0,
+            connected: 0,
+            passed:    [0, 0, 0, 0, 1, 0, 0, 0]
+        });
+        assert_eq!(structure.black, PawnFeatures {
+            doubled:   0,
+            isolated:  0,
+            backward:  0,
+            connected: 3,
+            passed:    [0; 8]
+        });
+        assert_eq!(structure.white_passed, 1 << 36);
+        assert_eq!(structure.black_passed, 0);
+    }
+
+    #[test]
+    fn backward_pawn() {
+        // The d-pawn has no pawn level with it or behind it on the c or e files, and e5 covers
+        // its stop square. It still defends c4, so c4 is connected but d3 isn't
+        let structure: PawnStructure = PawnStructure::new(squares("c4 d3"), squares("e5"));
+
+        assert_eq!(structure.white.backward, 1);
+        assert_eq!(structure.white.connected, 1);
+        assert_eq!(structure.white.isolated, 0);
+    }
+
+    #[test]
+    fn hash_table_caches_structures() {
+        let (white_pawns, black_pawns) = pawns();
+        let mut table: PawnHashTable = PawnHashTable::new(1000);
+        assert_eq!(table.entries.len(), 1024);
+
+        let first: PawnStructure = table.probe(white_pawns, black_pawns);
+        let second: PawnStructure = table.probe(white_pawns, black_pawns);
+        assert_eq!(first, second);
+        assert_eq!(first, PawnStructure::new(white_pawns, black_pawns));
+        assert_eq!((table.hits, table.misses), (1, 1));
+
+        table.clear();
+        assert_eq!((table.hits, table.misses), (0, 0));
+        table.probe(white_pawns, black_pawns);
+        assert_eq!((table.hits, table.misses), (0, 1));
+    }
+}
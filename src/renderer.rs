@@ -1,60 +1,307 @@
 use std::error::Error;
-use std::io::{self, Stdout, Write};
+use std::time::Duration;
 
-use crossterm::{
-    ExecutableCommand, QueueableCommand,
-    terminal, cursor, style::{self, Stylize}
-};
-use crossterm::style::{Color::{self, White, Reset, Black, Green, Blue}, Colors};
+use crossterm::style::Color;
 
+use crate::backend::{Backend, Style, TerminalBackend};
 use crate::board::Board;
-use crate::bit_functions::{iterate_over, bit_to_coord};
+use crate::bit_functions::{count_bits, iterate_over, bit_to_coord};
+use crate::engine::format_eval;
+use crate::theme::{Palette, Theme};
+
+// Screen layout - the board is drawn from the top left corner, with its rank labels to the left
+// and file labels underneath, then the status line and the input line below that. The eval bar
+// and the side panel are to the right of the board
+const BOARD_LEFT: u16 = 2;
+const FILE_LABEL_ROW: u16 = 8;
+const EVAL_BAR_LEFT: u16 = 19;
+const PANEL_LEFT: u16 = 22;
+const PANEL_WIDTH: u16 = 24; // The panel is left out of terminals too narrow to fit it
+const PANEL_MOVE_ROWS: usize = 5;
+pub const STATUS_ROW: u16 = 9;
+pub const INPUT_ROW: u16 = 10;
+
+// What a square was last drawn as, to tell which squares need drawing again
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Cell {
+    symbol: char,
+    marker: char, // Drawn after the symbol, to fill out the square's width
+    style:  Style // Reverse video for highlights without colour
+}
+
+// Highlights in order of precedence, for squares with more than one
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Highlight {
+    Cursor,
+    Check,
+    Destination,
+    LastMove
+}
 
-pub struct Renderer {
-    stdout: Stdout
+// Draws to the terminal unless given another backend, eg. a TextBackend to read the screen
+// back as text
+pub struct Renderer<B: Backend = TerminalBackend> {
+    backend:      B,
+    width:        u16,
+    theme:        Theme,
+    flipped:      bool, // Black at the bottom of the board rather than white
+
+    // Squares drawn in highlight colours rather than the usual light and dark ones
+    last_move:    u64, // From and to squares of the move that led to the position
+    destinations: u64, // Where a piece picked out by the player can move to
+    check:        u64, // The king in check, if there is one
+    cursor:       u64, // Square picked out with the arrow keys, once they've been used
+
+    // Shown in the side panel
+    move_list:    Vec<String>,                // Moves of the game so far, in standard notation
+    eval:         Option<f64>,                // Latest search evaluation, from white's point of view
+    clocks:       Option<(Duration, Duration)>, // Time left for white and black, with a time control
+
+    // What is on screen, so that redrawing only repaints what has changed - which matters over
+    // slow connections, where repainting the whole board flickers. None where nothing is known
+    // to be on screen, eg. after the terminal is resized
+    drawn:         [Option<Cell>; 64],
+    drawn_flipped: Option<bool>,       // Orientation the labels were drawn in
+    drawn_bar:     Option<(u16, bool)>, // Filled half squares of the eval bar, and its orientation
+    drawn_panel:   Vec<String>
 }
 
 impl Renderer {
-    pub fn new() -> Result<Renderer, Box<dyn Error>> {
-        let mut stdout = io::stdout();
-
-        stdout.execute(terminal::Clear(terminal::ClearType::All))?;
-
-        for y in 0..8 {
-            for x in 0..8 {
-                if (x + y) % 2 == 0 {
-                    stdout
-                        .queue(cursor::MoveTo(2*x, y))?
-                        .queue(style::PrintStyledContent("██".green()))?;
-                } else {
-                    stdout
-                        .queue(cursor::MoveTo(2*x, y))?
-                        .queue(style::PrintStyledContent("██".blue()))?;
-                }
+    pub fn new(theme: Theme) -> Result<Renderer, Box<dyn Error>> {
+        Renderer::with_backend(TerminalBackend::new(), theme)
+    }
+}
+
+impl<B: Backend> Renderer<B> {
+    pub fn with_backend(mut backend: B, theme: Theme) -> Result<Renderer<B>, Box<dyn Error>> {
+
+        backend.clear_all()?;
+
+        let width: u16 = backend.size().0;
+
+        let mut r = Renderer {
+            backend,
+            width,
+            theme,
+            flipped:      false,
+            last_move:    0,
+            destinations: 0,
+            check:        0,
+            cursor:       0,
+            move_list:    vec![],
+            eval:         None,
+            clocks:       None,
+            drawn:         [None; 64],
+            drawn_flipped: None,
+            drawn_bar:     None,
+            drawn_panel:   vec![]
+        };
+
+        r.clear_board()?;
+        r.reset_cursor()?;
+
+        Ok(r)
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn resize(&mut self, columns: u16) -> Result<(), Box<dyn Error>> {
+
+        // Terminals differ in what they keep of the screen when resized, so it is cleared and
+        // everything is drawn again from scratch on the next parse_board
+        self.width = columns;
+        self.backend.clear_all()?;
+        self.invalidate();
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.drawn = [None; 64];
+        self.drawn_flipped = None;
+        self.drawn_bar = None;
+        self.drawn_panel.clear();
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        // Everything is drawn again in the new theme on the next redraw
+        self.theme = theme;
+        self.invalidate();
+    }
+
+    pub fn is_flipped(&self) -> bool {
+        self.flipped
+    }
+
+    pub fn set_flipped(&mut self, flipped: bool) {
+        // Takes effect on the next redraw
+        self.flipped = flipped;
+    }
+
+    pub fn flip(&mut self) {
+        self.flipped = !self.flipped;
+    }
+
+    pub fn set_last_move(&mut self, squares: Option<(u64, u64)>) {
+        self.last_move = squares.map(|(from, to)| from | to).unwrap_or(0);
+    }
+
+    pub fn last_move(&self) -> u64 {
+        self.last_move
+    }
+
+    pub fn set_destinations(&mut self, squares: u64) {
+        self.destinations = squares;
+    }
+
+    pub fn set_move_list(&mut self, move_list: &[String]) {
+        self.move_list = move_list.to_vec();
+    }
+
+    pub fn set_eval(&mut self, eval: Option<f64>) {
+        self.eval = eval;
+    }
+
+    pub fn set_clocks(&mut self, clocks: Option<(Duration, Duration)>) {
+        self.clocks = clocks;
+    }
+
+    fn highlight(&self, bit: u64) -> Option<Highlight> {
+        [
+            (self.cursor, Highlight::Cursor),
+            (self.check, Highlight::Check),
+            (self.destinations, Highlight::Destination),
+            (self.last_move, Highlight::LastMove)
+        ].into_iter().find(|(squares, _)| bit & squares != 0).map(|(_, highlight)| highlight)
+    }
+
+    fn cell(&self, index: (u16, u16), piece: Option<(char, char)>) -> Cell {
+
+        // How a square looks with the given piece (type and colour) on it, if any
+        let bit: u64 = 1 << (8*index.1 + index.0);
+        let light: bool = (index.0 + index.1) % 2 == 1;
+        let highlight: Option<Highlight> = self.highlight(bit);
+
+        let symbol: char = match piece {
+            Some((piece, colour)) => self.theme.piece_symbol(piece, colour),
+            None => ' '
+        };
+
+        match self.theme.palette {
+            Some(palette) => {
+                // Light and dark versions of each highlight keep the squares' pattern visible through it
+                let shade: usize = if light {0} else {1};
+                let background: Color = match highlight {
+                    Some(Highlight::Cursor) => palette.cursor[shade],
+                    Some(Highlight::Check) => palette.check,
+                    Some(Highlight::Destination) => palette.destinations[shade],
+                    Some(Highlight::LastMove) => palette.last_move[shade],
+                    None => if light {palette.light} else {palette.dark}
+                };
+                let foreground: Color = match piece {
+                    Some((_, 'w')) => palette.white_pieces,
+                    _ => palette.black_pieces
+                };
+
+                Cell {symbol, marker: ' ', style: Style {foreground, background, reverse: false}}
+            },
+            None => {
+                // Without colour, dark squares are dotted and highlights are in reverse video,
+                // with destinations also marked since they are usually empty squares
+                let symbol: char = if symbol == ' ' && !light {if self.theme.ascii {'.'} else {'·'}} else {symbol};
+                let marker: char = if highlight == Some(Highlight::Destination) {'*'} else {' '};
+
+                Cell {symbol, marker, style: Style {reverse: highlight.is_some(), ..Style::PLAIN}}
             }
         }
+    }
 
-        stdout.queue(cursor::MoveTo(0, 8))?;
-        stdout.flush()?;
+    fn screen_position(&self, index: (u16, u16)) -> (u16, u16) {
+        // Where the square at (file, rank) is drawn. Flipping turns the board round completely,
+        // so files are reversed as well as ranks, and the light and dark squares stay in place
+        if self.flipped {
+            (BOARD_LEFT + 2*(7-index.0), index.1)
+        } else {
+            (BOARD_LEFT + 2*index.0, 7-index.1)
+        }
+    }
 
-        let r = Renderer {
-            stdout
-        };
+    pub fn square_at(&self, column: u16, row: u16) -> Option<u64> {
 
-        Ok(r)
+        // The square drawn at a terminal cell, if the cell is on the board
+        if !(BOARD_LEFT..BOARD_LEFT + 16).contains(&column) || row >= 8 {
+            return None;
+        }
+
+        let x: u16 = (column - BOARD_LEFT) / 2;
+        let (file, rank) = if self.flipped {(7-x, row)} else {(x, 7-row)};
+
+        Some(1 << (8*rank + file))
+    }
+
+    pub fn cursor(&self) -> Option<u64> {
+        if self.cursor == 0 {None} else {Some(self.cursor)}
+    }
+
+    pub fn move_cursor(&mut self, right: i16, down: i16) {
+
+        // Moves the cursor in the direction given on screen, which for a flipped board is the
+        // opposite direction on the board. The cursor starts out at the bottom of the e-file
+        if self.cursor == 0 {
+            self.cursor = self.square_at(BOARD_LEFT + 8, 7).unwrap_or(0);
+            return;
+        }
+
+        let (file, rank) = bit_to_coord(self.cursor);
+        let (x, y) = self.screen_position((file, rank));
+        let column: u16 = (x as i16 + 2*right).clamp(BOARD_LEFT as i16, BOARD_LEFT as i16 + 14) as u16;
+        let row: u16 = (y as i16 + down).clamp(0, 7) as u16;
+
+        self.cursor = self.square_at(column, row).unwrap_or(self.cursor);
+    }
+
+    fn draw_labels(&mut self) -> Result<(), Box<dyn Error>> {
+
+        // Rank numbers down the left and file letters along the bottom, in the order the
+        // board is currently drawn in
+        for i in 0..8 {
+            let (x, y) = self.screen_position((i, i));
+            let rank: char = (b'1' + i as u8) as char;
+            let file: char = (b'a' + i as u8) as char;
+
+            self.backend.print(0, y, &format!("{} ", rank), Style::PLAIN)?;
+            self.backend.print(x, FILE_LABEL_ROW, &format!("{} ", file), Style::PLAIN)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_orientation(&mut self) -> Result<(), Box<dyn Error>> {
+
+        // Turning the board round moves every square, so everything is drawn again
+        if self.drawn_flipped != Some(self.flipped) {
+            self.drawn = [None; 64];
+            self.drawn_bar = None;
+            self.draw_labels()?;
+            self.drawn_flipped = Some(self.flipped);
+        }
+
+        Ok(())
     }
 
     pub fn clear_board(&mut self) -> Result<(), Box<dyn Error>> {
 
-        let mut bg_colour: Color;
+        self.draw_orientation()?;
 
-        for y in 0..8 {
-            for x in 0..8 {
-                bg_colour = if (x+y) % 2 == 0 {Green} else {Blue};
-                self.stdout
-                    .queue(cursor::MoveTo(2*x, y))?
-                    .queue(style::SetBackgroundColor(bg_colour))?
-                    .queue(style::Print("  "))?;
+        for rank in 0..8 {
+            for file in 0..8 {
+                self.clear_square((file, rank))?;
             }
         }
 
@@ -63,11 +310,8 @@ impl Renderer {
 
     pub fn clear_square(&mut self, index: (u16, u16)) -> Result<(), Box<dyn Error>> {
 
-        let bg_colour = if (index.0 + index.1) % 2 == 0 {Green} else {Blue};
-        self.stdout
-            .queue(cursor::MoveTo(2*index.0, index.1))?
-            .queue(style::SetBackgroundColor(bg_colour))?
-            .queue(style::Print("  "))?;
+        let cell: Cell = self.cell(index, None);
+        self.draw_cell(index, cell)?;
 
         self.reset_cursor()?;
 
@@ -76,98 +320,317 @@ impl Renderer {
 
     pub fn write_to_square(&mut self, index: (u16, u16), piece: char, colour: char) -> Result<(), Box<dyn Error>> {
 
-        let bg_colour = if (index.0 + index.1) % 2 == 1 {Green} else {Blue};
-
-        let symbol = match piece {
-            'k' => '♚',
-            'q' => '♛',
-            'r' => '♜',
-            'b' => '♝',
-            'n' => '♞',
-            'p' => '♟',
-            _ =>  unreachable!("Invalid piece type")
-        };
+        let cell: Cell = self.cell(index, Some((piece, colour)));
+        self.draw_cell(index, cell)?;
 
-        if colour == 'w' {
-            self.stdout.queue(style::SetForegroundColor(White))?;
-        } else {
-            self.stdout.queue(style::SetForegroundColor(Black))?;
-        }
-
-        self.stdout
-            .queue(cursor::MoveTo(2*index.0, 7-index.1))?
-            .queue(style::SetBackgroundColor(bg_colour))?
-            .queue(style::Print(format!("{} ", symbol)))?;
-        
         self.reset_cursor()?;
 
         Ok(())
     }
 
-    pub fn reset_cursor(&mut self) -> Result<(), Box<dyn Error>> {
-        self.stdout
-            .queue(cursor::MoveTo(0, 8))?
-            .queue(style::SetColors(Colors::new(Reset, Reset)))?
-            .flush()?;
+    fn draw_cell(&mut self, index: (u16, u16), cell: Cell) -> Result<(), Box<dyn Error>> {
+
+        // Only queued for drawing if the square doesn't already look like this
+        let square: usize = (8*index.1 + index.0) as usize;
+        if self.drawn[square] == Some(cell) {
+            return Ok(());
+        }
+
+        let (x, y) = self.screen_position(index);
+        self.backend.print(x, y, &format!("{}{}", cell.symbol, cell.marker), cell.style)?;
+
+        self.drawn[square] = Some(cell);
 
         Ok(())
     }
 
-    pub fn parse_board(&mut self, board: &Board) -> Result<(), Box<dyn Error>> {
+    pub fn draw_panel(&mut self, board: &Board) -> Result<(), Box<dyn Error>> {
 
-        self.clear_board()?;
+        // Redraws the eval bar and side panel without touching the board, so that clocks can be
+        // kept up to date. The cursor is put back where it was, as this can happen while typing
+        self.backend.save_cursor()?;
 
-        for p in iterate_over(board.white_pawns).iter() {
-            self.write_to_square(bit_to_coord(*p), 'p', 'w')?;
+        if self.width > EVAL_BAR_LEFT {
+            self.draw_eval_bar()?;
         }
-
-        for p in iterate_over(board.black_pawns).iter() {
-            self.write_to_square(bit_to_coord(*p), 'p', 'b')?;
+        if self.width < PANEL_LEFT + PANEL_WIDTH {
+            self.backend.restore_cursor()?;
+            self.backend.flush()?;
+            return Ok(());
         }
 
-        for p in iterate_over(board.white_bishops).iter() {
-            self.write_to_square(bit_to_coord(*p), 'b', 'w')?;
-        }
+        let side: &str = if board.to_move == 1 {"White to move"} else {"Black to move"};
+        let eval: String = self.eval.map(format_eval).unwrap_or_default();
 
-        for p in iterate_over(board.black_bishops).iter() {
-            self.write_to_square(bit_to_coord(*p), 'b', 'b')?;
-        }
+        let clocks: String = match self.clocks {
+            Some((white, black)) => format!("White {}  Black {}", format_clock(white), format_clock(black)),
+            None => String::new()
+        };
 
-        for p in iterate_over(board.white_knights).iter() {
-            self.write_to_square(bit_to_coord(*p), 'n', 'w')?;
+        // Each side's captures, with the material difference beside whoever is ahead
+        let (black_material, white_captures) = captures(board, 0, &self.theme);
+        let (white_material, black_captures) = captures(board, 1, &self.theme);
+        let difference: i32 = white_material - black_material;
+        let advantage = |sign: i32| if difference * sign > 0 {format!(" +{}", difference.abs())} else {String::new()};
+
+        let mut lines: Vec<String> = vec![
+            format!("{:<16}{}", side, eval),
+            clocks,
+            format!("White: {}{}", white_captures, advantage(1)),
+            format!("Black: {}{}", black_captures, advantage(-1))
+        ];
+
+        // The latest moves, a pair to a line
+        let pairs: Vec<String> = self.move_list.chunks(2).enumerate().map(|(i, pair)| {
+            format!("{:>3}. {:<8}{}", i + 1, pair[0], pair.get(1).map(String::as_str).unwrap_or(""))
+        }).collect();
+        lines.extend(pairs.iter().skip(pairs.len().saturating_sub(PANEL_MOVE_ROWS)).cloned());
+        lines.resize(lines.len().max(4 + PANEL_MOVE_ROWS), String::new());
+
+        for (row, line) in lines.iter().enumerate() {
+            if self.drawn_panel.get(row) == Some(line) {
+                continue;
+            }
+            self.backend.clear_line(PANEL_LEFT, row as u16)?;
+            self.backend.print(PANEL_LEFT, row as u16, line, Style::PLAIN)?;
         }
+        self.drawn_panel = lines;
 
-        for p in iterate_over(board.black_knights).iter() {
-            self.write_to_square(bit_to_coord(*p), 'n', 'b')?;
-        }
+        self.backend.restore_cursor()?;
+        self.backend.flush()?;
 
-        for p in iterate_over(board.white_rooks).iter() {
-            self.write_to_square(bit_to_coord(*p), 'r', 'w')?;
-        }
+        Ok(())
+    }
+
+    fn draw_eval_bar(&mut self) -> Result<(), Box<dyn Error>> {
+
+        // White's share of the bar grows with the evaluation, in half squares, from white's
+        // side of the board - a pawn or two ahead fills around three quarters of it
+        let share: f64 = match self.eval {
+            Some(eval) => 1.0 / (1.0 + (-eval / 2.0).exp()),
+            None => 0.5
+        };
+        let filled: u16 = (share * 16.0).round() as u16;
 
-        for p in iterate_over(board.black_rooks).iter() {
-            self.write_to_square(bit_to_coord(*p), 'r', 'b')?;
+        if self.drawn_bar == Some((filled, self.flipped)) {
+            return Ok(());
         }
+        self.drawn_bar = Some((filled, self.flipped));
 
-        for p in iterate_over(board.white_queens).iter() {
-            self.write_to_square(bit_to_coord(*p), 'q', 'w')?;
+        let style: Style = match self.theme.palette {
+            Some(Palette {white_pieces, black_pieces, ..}) => Style {foreground: white_pieces, background: black_pieces, reverse: false},
+            None => Style::PLAIN
+        };
+
+        for i in 0..8 {
+            let symbol: &str = match (filled.saturating_sub(2*i), self.theme.ascii) {
+                (0, _) => " ",
+                (1, true) => "=",
+                (1, false) if self.flipped => "▀",
+                (1, false) => "▄",
+                (_, true) => "#",
+                (_, false) => "█"
+            };
+            let row: u16 = if self.flipped {i} else {7-i};
+
+            self.backend.print(EVAL_BAR_LEFT, row, symbol, style)?;
         }
 
-        for p in iterate_over(board.black_queens).iter() {
-            self.write_to_square(bit_to_coord(*p), 'q', 'b')?;
+        Ok(())
+    }
+
+    pub fn reset_cursor(&mut self) -> Result<(), Box<dyn Error>> {
+        self.backend.set_cursor(0, STATUS_ROW)?;
+        self.backend.flush()?;
+
+        Ok(())
+    }
+
+    pub fn parse_board(&mut self, board: &Board) -> Result<(), Box<dyn Error>> {
+
+        self.check = 0;
+        if board.white_check {
+            self.check |= board.white_king;
+        }
+        if board.black_check {
+            self.check |= board.black_king;
         }
 
-        for p in iterate_over(board.white_king).iter() {
-            self.write_to_square(bit_to_coord(*p), 'k', 'w')?;
+        self.draw_orientation()?;
+
+        // Work out what every square should look like, then draw only the ones that differ
+        // from what is already on screen
+        let pieces: [(u64, char, char); 12] = [
+            (board.white_pawns, 'p', 'w'), (board.black_pawns, 'p', 'b'),
+            (board.white_bishops, 'b', 'w'), (board.black_bishops, 'b', 'b'),
+            (board.white_knights, 'n', 'w'), (board.black_knights, 'n', 'b'),
+            (board.white_rooks, 'r', 'w'), (board.black_rooks, 'r', 'b'),
+            (board.white_queens, 'q', 'w'), (board.black_queens, 'q', 'b'),
+            (board.white_king, 'k', 'w'), (board.black_king, 'k', 'b')
+        ];
+        let mut squares: [Option<(char, char)>; 64] = [None; 64];
+        for (bits, piece, colour) in pieces {
+            for p in iterate_over(bits) {
+                squares[p.trailing_zeros() as usize] = Some((piece, colour));
+            }
         }
 
-        for p in iterate_over(board.black_king).iter() {
-            self.write_to_square(bit_to_coord(*p), 'k', 'b')?;
+        for (square, piece) in squares.into_iter().enumerate() {
+            let index: (u16, u16) = bit_to_coord(1 << square);
+            let cell: Cell = self.cell(index, piece);
+            self.draw_cell(index, cell)?;
         }
 
+        self.draw_panel(board)?;
+
         self.reset_cursor()?;
 
         Ok(())
     }
 
-}
\ No newline at end of file
+}
+
+fn captures(board: &Board, colour: u8, theme: &Theme) -> (i32, String) {
+
+    // Material a side has left, and the symbols of its pieces taken so far. Promoted pieces
+    // just count as pieces that weren't captured
+    let pieces: [(u64, u32, i32, char); 5] = if colour == 1 {
+        [(board.white_queens, 1, 9, 'q'), (board.white_rooks, 2, 5, 'r'), (board.white_bishops, 2, 3, 'b'), (board.white_knights, 2, 3, 'n'), (board.white_pawns, 8, 1, 'p')]
+    } else {
+        [(board.black_queens, 1, 9, 'q'), (board.black_rooks, 2, 5, 'r'), (board.black_bishops, 2, 3, 'b'), (board.black_knights, 2, 3, 'n'), (board.black_pawns, 8, 1, 'p')]
+    };
+
+    let mut material: i32 = 0;
+    let mut taken: String = String::new();
+
+    for (bits, start, value, piece) in pieces {
+        let count: u32 = count_bits(bits) as u32;
+        material += value * count as i32;
+        for _ in count..start {
+            taken.push(theme.piece_symbol(piece, if colour == 1 {'w'} else {'b'}));
+        }
+    }
+
+    (material, taken)
+}
+
+fn format_clock(time: Duration) -> String {
+    let seconds: u64 = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::style::Color::{White, Black, Green, Blue, Red};
+    use crate::backend::TextBackend;
+
+    fn render(board: &Board, theme: &str, ascii: bool, flipped: bool) -> Renderer<TextBackend> {
+        let mut renderer: Renderer<TextBackend> = Renderer::with_backend(TextBackend::new(80, 12), Theme::named(theme, ascii).unwrap()).unwrap();
+        renderer.set_flipped(flipped);
+        renderer.parse_board(board).unwrap();
+        renderer
+    }
+
+    fn screen(rows: &[&str]) -> String {
+        // The rows given, then blank ones down to the bottom of the grid
+        let mut rows: Vec<&str> = rows.to_vec();
+        rows.resize(12, "");
+        rows.join("\n")
+    }
+
+    #[test]
+    fn start_position() {
+        let renderer: Renderer<TextBackend> = render(&Board::new(), "classic", false, false);
+
+        assert_eq!(renderer.backend().text(), screen(&[
+            "8 ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜     White to move",
+            "7 ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟",
+            "6                     White:",
+            "5                     Black:",
+            "4                  █",
+            "3                  █",
+            "2 ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟  █",
+            "1 ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜  █",
+            "  a b c d e f g h"
+        ]));
+
+        // The pawn on e2, on a light square, and the rook on h8, on a dark one
+        assert_eq!(renderer.backend().style_at(10, 6), Some(Style {foreground: White, background: Green, reverse: false}));
+        assert_eq!(renderer.backend().style_at(16, 0), Some(Style {foreground: Black, background: Blue, reverse: false}));
+        assert_eq!(renderer.backend().cursor(), (0, STATUS_ROW));
+    }
+
+    #[test]
+    fn flipped_board() {
+        let renderer: Renderer<TextBackend> = render(&Board::new(), "classic", false, true);
+
+        assert_eq!(renderer.backend().text(), screen(&[
+            "1 ♜ ♞ ♝ ♚ ♛ ♝ ♞ ♜  █  White to move",
+            "2 ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟  █",
+            "3                  █  White:",
+            "4                  █  Black:",
+            "5",
+            "6",
+            "7 ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟",
+            "8 ♜ ♞ ♝ ♚ ♛ ♝ ♞ ♜",
+            "  h g f e d c b a"
+        ]));
+
+        // a1 is in the top right corner, and still a dark square
+        assert_eq!(renderer.backend().style_at(16, 0), Some(Style {foreground: White, background: Blue, reverse: false}));
+        assert_eq!(renderer.square_at(16, 0), Some(1));
+    }
+
+    #[test]
+    fn ascii_without_colour() {
+        let board: Board = Board::new();
+        let e4: Board = board.parse_input(&"e4".to_string()).unwrap();
+
+        let mut renderer: Renderer<TextBackend> = Renderer::with_backend(TextBackend::new(80, 12), Theme::named("none", true).unwrap()).unwrap();
+        renderer.set_last_move(Some(board.move_squares(&e4)));
+        renderer.set_move_list(&["e4".to_string()]);
+        renderer.parse_board(&e4).unwrap();
+
+        assert_eq!(renderer.backend().text(), screen(&[
+            "8 r n b q k b n r     Black to move",
+            "7 p p p p p p p p",
+            "6   .   .   .   .     White:",
+            "5 .   .   .   .       Black:",
+            "4   .   . P .   .  #    1. e4",
+            "3 .   .   .   .    #",
+            "2 P P P P   P P P  #",
+            "1 R N B Q K B N R  #",
+            "  a b c d e f g h"
+        ]));
+
+        // The last move is in reverse video, and nothing else is
+        let reverse: Style = Style {reverse: true, ..Style::PLAIN};
+        assert_eq!(renderer.backend().style_at(10, 6), Some(reverse));
+        assert_eq!(renderer.backend().style_at(10, 4), Some(reverse));
+        assert_eq!(renderer.backend().style_at(12, 4), Some(Style::PLAIN));
+    }
+
+    #[test]
+    fn check_highlight() {
+        let board: Board = Board::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        let renderer: Renderer<TextBackend> = render(&board, "classic", false, false);
+
+        assert_eq!(renderer.backend().text(), screen(&[
+            "8 ♜ ♞ ♝   ♚ ♝ ♞ ♜     White to move",
+            "7 ♟ ♟ ♟ ♟   ♟ ♟ ♟",
+            "6                     White:",
+            "5         ♟           Black:",
+            "4             ♟ ♛  █",
+            "3           ♟      █",
+            "2 ♟ ♟ ♟ ♟ ♟     ♟  █",
+            "1 ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜  █",
+            "  a b c d e f g h"
+        ]));
+
+        // The king in check is highlighted, but not the queen giving check or the other king
+        assert_eq!(renderer.backend().style_at(10, 7), Some(Style {foreground: White, background: Red, reverse: false}));
+        assert_eq!(renderer.backend().style_at(16, 4), Some(Style {foreground: Black, background: Blue, reverse: false}));
+        assert_eq!(renderer.backend().style_at(10, 0), Some(Style {foreground: Black, background: Green, reverse: false}));
+    }
+}
@@ -0,0 +1,235 @@
+use std::error::Error;
+
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty_syzygy::{Tablebase, Wdl};
+
+use crate::board::Board;
+use crate::bit_functions::{coord_to_bit, count_bits};
+
+// Syzygy endgame tablebase probing
+//
+// Decoding the tables themselves is left to shakmaty-syzygy, so boards are handed over as FEN.
+// Only WDL (win/draw/loss) tables are needed for probing inside the search, while picking
+// moves at the root also needs the DTZ (distance to zeroing move) tables
+
+// Below checkmate, so that the search still prefers mating over entering a won ending
+pub const TABLEBASE_WIN: f64 = 5000.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TablebaseMove {
+    pub board: Board,
+    pub wdl:   i32, // 2 win, 1 win only without the 50 move rule, 0 draw, -1 and -2 likewise for losses
+    pub dtz:   i32  // Plies to the next capture or pawn move, signed as wdl
+}
+
+pub struct EndgameTablebase {
+    tablebase: Tablebase<Chess>
+}
+
+impl EndgameTablebase {
+    pub fn new(path: &str) -> Result<EndgameTablebase, Box<dyn Error>> {
+
+        let mut tablebase: Tablebase<Chess> = Tablebase::new();
+
+        if tablebase.add_directory(path)? == 0 {
+            return Err(format!("No Syzygy tables found in {}", path).into());
+        }
+
+        Ok(EndgameTablebase {
+            tablebase
+        })
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.tablebase.max_pieces()
+    }
+
+    fn to_position(&self, board: &Board) -> Option<Chess> {
+
+        if count_bits(board.all_pieces) as usize > self.max_pieces() {
+            return None;
+        }
+
+        let fen: Fen = Fen::from_ascii(board.to_fen().as_bytes()).ok()?;
+        fen.into_position(CastlingMode::Standard).ok()
+    }
+
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+
+        // From the side to move's point of view. The halfmove clock isn't tracked, so every
+        // position is treated as if a capture or pawn move has just been made
+        let position: Chess = self.to_position(board)?;
+        self.tablebase.probe_wdl_after_zeroing(&position).ok()
+    }
+
+    pub fn score(&self, board: &Board) -> Option<f64> {
+
+        // Exact score from white's point of view. The 50 move rule isn't implemented, so wins
+        // it would frustrate still count as wins, just below unconditional ones
+        let score: f64 = match self.probe_wdl(board)? {
+            Wdl::Win => TABLEBASE_WIN,
+            Wdl::CursedWin => TABLEBASE_WIN - 100.0,
+            Wdl::Draw => 0.0,
+            Wdl::BlessedLoss => -TABLEBASE_WIN + 100.0,
+            Wdl::Loss => -TABLEBASE_WIN
+        };
+
+        Some(if board.to_move == 1 {score} else {-score})
+    }
+
+    pub fn best_move(&self, board: &Board) -> Option<TablebaseMove> {
+
+        let position: Chess = self.to_position(board)?;
+        let (best, _) = self.tablebase.best_move(&position).ok()??;
+
+        // DTZ of the position before the move, for the side to move - positive if it wins and
+        // negative if it loses. After the best move, the opponent's DTZ is a ply nearer zero
+        // with the opposite sign, eg. 5 here means -4 for the opponent after the move
+        let dtz: i32 = self.tablebase.probe_dtz(&position).ok()?.ignore_rounding().0;
+
+        let uci: String = best.to_uci(CastlingMode::Standard).to_string();
+        let squares: Vec<u64> = uci.as_bytes()[..4].chunks(2).map(|square| {
+            coord_to_bit(((square[1] - b'1') as u16, (square[0] - b'a') as u16))
+        }).collect();
+
+        // Only promotion to a queen is supported, so underpromotions are left to the search
+        if uci.len() == 5 && !uci.ends_with('q') {
+            return None;
+        }
+
+        let next: Board = board.generate_move_list().into_iter().find(|next| board.move_squares(next) == (squares[0], squares[1]))?;
+
+        // The move keeps the position's result, which is what the DTZ sign reflects
+        let wdl: i32 = match self.probe_wdl(board)? {
+            Wdl::Win => 2,
+            Wdl::CursedWin => 1,
+            Wdl::Draw => 0,
+            Wdl::BlessedLoss => -1,
+            Wdl::Loss => -2
+        };
+
+        Some(TablebaseMove {
+            board: next,
+            wdl,
+            dtz
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // KQvK, KRvK and KQvKR tables, small enough to bundle. They were built locally by
+    // retrograde analysis rather than downloaded, and checked against shakmaty-syzygy's probing
+    // for every legal position, and against the official tables' values below
+    fn tablebase() -> EndgameTablebase {
+        EndgameTablebase::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy")).unwrap()
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn loads_the_bundled_tables() {
+        assert_eq!(tablebase().max_pieces(), 4);
+        assert!(EndgameTablebase::new(env!("CARGO_MANIFEST_DIR")).is_err());
+    }
+
+    #[test]
+    fn probes_wins_losses_and_draws() {
+        let tb: EndgameTablebase = tablebase();
+
+        assert_eq!(tb.probe_wdl(&board("8/8/8/4k3/8/8/8/KQ6 w - - 0 1")), Some(Wdl::Win));
+        assert_eq!(tb.probe_wdl(&board("8/8/8/4k3/8/8/8/KQ6 b - - 0 1")), Some(Wdl::Loss));
+        assert_eq!(tb.probe_wdl(&board("8/8/8/3k4/8/8/8/R3K3 b - - 0 1")), Some(Wdl::Loss));
+
+        // Black takes the undefended queen, or is stalemated
+        assert_eq!(tb.probe_wdl(&board("8/8/8/8/8/8/3kQ3/7K b - - 0 1")), Some(Wdl::Draw));
+        assert_eq!(tb.probe_wdl(&board("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1")), Some(Wdl::Draw));
+
+        // Too many pieces for the tables
+        assert_eq!(tb.probe_wdl(&Board::new()), None);
+    }
+
+    #[test]
+    fn scores_from_whites_point_of_view() {
+        let tb: EndgameTablebase = tablebase();
+
+        assert_eq!(tb.score(&board("8/8/8/4k3/8/8/8/KQ6 w - - 0 1")), Some(TABLEBASE_WIN));
+        assert_eq!(tb.score(&board("8/8/8/4k3/8/8/8/KQ6 b - - 0 1")), Some(TABLEBASE_WIN));
+        assert_eq!(tb.score(&board("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1")), Some(0.0));
+    }
+
+    #[test]
+    fn matches_the_official_tables() {
+        let tb: EndgameTablebase = tablebase();
+
+        // WDL and DTZ from the official Syzygy tables, as listed in shakmaty-syzygy's tests
+        let expected: [(&str, i32, i32); 8] = [
+            ("8/8/8/8/8/1q3k2/8/2R4K w - - 0 1", -2, -8),
+            ("8/5K2/3k1r2/8/8/8/2Q5/8 w - - 0 1", 2, 1),
+            ("8/2k5/8/8/8/4Qr2/6K1/8 b - - 0 1", 2, 1),
+            ("6q1/8/6K1/4R3/1k6/8/8/8 w - - 0 1", -2, -46),
+            ("8/5q2/8/2K5/8/8/k7/1R6 w - - 0 1", -2, -54),
+            ("K7/3q4/5R2/5k2/8/8/8/8 b - - 0 1", 2, 1),
+            ("1R6/3k4/8/8/8/1K6/8/2q5 b - - 0 1", 2, 3),
+            ("8/8/8/2R5/1K6/8/5k2/8 w - - 0 1", 2, 21)
+        ];
+
+        for (fen, wdl, dtz) in expected {
+            let best: TablebaseMove = tb.best_move(&board(fen)).unwrap();
+            assert_eq!((best.wdl, best.dtz), (wdl, dtz), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn best_move_wins_the_rook() {
+        let tb: EndgameTablebase = tablebase();
+
+        // Kf7xf6, leaving KQvK
+        let start: Board = board("8/5K2/3k1r2/8/8/8/2Q5/8 w - - 0 1");
+        let best: TablebaseMove = tb.best_move(&start).unwrap();
+        assert_eq!(start.move_squares(&best.board), (1 << 53, 1 << 45));
+        assert_eq!(tb.probe_wdl(&best.board), Some(Wdl::Loss));
+
+        // Black's king takes the rook the same way, leaving KQvK the other way round
+        let start: Board = board("K7/3q4/5R2/5k2/8/8/8/8 b - - 0 1");
+        let best: TablebaseMove = tb.best_move(&start).unwrap();
+        assert_eq!(start.move_squares(&best.board), (1 << 37, 1 << 45));
+        assert_eq!(tb.probe_wdl(&best.board), Some(Wdl::Loss));
+    }
+
+    #[test]
+    fn best_move_mates_in_one() {
+        let start: Board = board("6k1/8/6K1/8/8/8/8/1Q6 w - - 0 1");
+        let best: TablebaseMove = tablebase().best_move(&start).unwrap();
+
+        // Qb1-b8
+        assert_eq!(start.move_squares(&best.board), (1 << 1, 1 << 57));
+        assert_eq!((best.wdl, best.dtz), (2, 1));
+        assert!(best.board.black_check && best.board.generate_move_list().is_empty());
+    }
+
+    #[test]
+    fn best_move_makes_progress() {
+        let tb: EndgameTablebase = tablebase();
+        let mut board: Board = board("8/8/8/8/3k4/8/8/R3K3 w - - 0 1");
+        let mut dtz: i32 = tb.best_move(&board).unwrap().dtz;
+
+        // Each side's best move brings the mate a ply closer, however black defends
+        while !board.generate_move_list().is_empty() {
+            let best: TablebaseMove = tb.best_move(&board).unwrap();
+            assert_eq!(best.dtz, dtz);
+            assert_eq!(best.wdl, if board.to_move == 1 {2} else {-2});
+
+            board = best.board;
+            dtz = if dtz > 0 {-(dtz - 1)} else {-dtz - 1};
+        }
+
+        assert!(board.black_check);
+        assert_eq!(dtz, 0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::env;
+
+use crossterm::style::Color::{self, Rgb, White, Black, Green, Blue, Yellow, DarkYellow, Cyan, DarkCyan, Magenta, DarkMagenta, Red};
+
+// Colour themes for the board
+//
+// A theme is a palette of colours, or none at all to draw without colour (as asked for by the
+// NO_COLOR environment variable), along with whether pieces are drawn as chess symbols or as
+// plain letters for terminals without the symbols
+
+pub const THEME_NAMES: [&str; 5] = ["classic", "wood", "contrast", "colourblind", "none"];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub light:        Color,
+    pub dark:         Color,
+    pub white_pieces: Color,
+    pub black_pieces: Color,
+
+    // Highlights, each as the colours for light and dark squares
+    pub last_move:    [Color; 2],
+    pub destinations: [Color; 2],
+    pub cursor:       [Color; 2],
+    pub check:        Color
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name:    &'static str,
+    pub palette: Option<Palette>, // None to draw without colour
+    pub ascii:   bool             // Letters rather than chess symbols for pieces
+}
+
+impl Theme {
+
+    pub fn named(name: &str, ascii: bool) -> Option<Theme> {
+
+        let name: &'static str = THEME_NAMES.into_iter().find(|theme| theme.eq_ignore_ascii_case(name))?;
+
+        let palette: Option<Palette> = match name {
+            "classic" => Some(Palette {
+                light:        Green,
+                dark:         Blue,
+                white_pieces: White,
+                black_pieces: Black,
+                last_move:    [Yellow, DarkYellow],
+                destinations: [Cyan, DarkCyan],
+                cursor:       [Magenta, DarkMagenta],
+                check:        Red
+            }),
+            // Softer colours that stay readable on light terminals
+            "wood" => Some(Palette {
+                light:        Rgb {r: 240, g: 217, b: 181},
+                dark:         Rgb {r: 181, g: 136, b: 99},
+                white_pieces: Rgb {r: 255, g: 255, b: 255},
+                black_pieces: Rgb {r: 0, g: 0, b: 0},
+                last_move:    [Rgb {r: 205, g: 210, b: 106}, Rgb {r: 170, g: 162, b: 58}],
+                destinations: [Rgb {r: 130, g: 200, b: 230}, Rgb {r: 90, g: 150, b: 190}],
+                cursor:       [Rgb {r: 220, g: 150, b: 220}, Rgb {r: 170, g: 100, b: 170}],
+                check:        Rgb {r: 230, g: 60, b: 50}
+            }),
+            // Grey squares halfway between the pieces' colours, so both stand out on either
+            "contrast" => Some(Palette {
+                light:        Rgb {r: 150, g: 150, b: 150},
+                dark:         Rgb {r: 110, g: 110, b: 110},
+                white_pieces: Rgb {r: 255, g: 255, b: 255},
+                black_pieces: Rgb {r: 0, g: 0, b: 0},
+                last_move:    [Rgb {r: 255, g: 215, b: 0}, Rgb {r: 200, g: 165, b: 0}],
+                destinations: [Rgb {r: 0, g: 200, b: 255}, Rgb {r: 0, g: 150, b: 210}],
+                cursor:       [Rgb {r: 255, g: 80, b: 255}, Rgb {r: 200, g: 0, b: 200}],
+                check:        Rgb {r: 255, g: 0, b: 0}
+            }),
+            // From the Okabe-Ito palette, which avoids the red/green pairs that are hard to tell
+            // apart with the common forms of colour blindness
+            "colourblind" => Some(Palette {
+                light:        Rgb {r: 230, g: 159, b: 0},
+                dark:         Rgb {r: 0, g: 114, b: 178},
+                white_pieces: Rgb {r: 255, g: 255, b: 255},
+                black_pieces: Rgb {r: 0, g: 0, b: 0},
+                last_move:    [Rgb {r: 240, g: 228, b: 66}, Rgb {r: 190, g: 180, b: 40}],
+                destinations: [Rgb {r: 86, g: 180, b: 233}, Rgb {r: 50, g: 140, b: 190}],
+                cursor:       [Rgb {r: 0, g: 158, b: 115}, Rgb {r: 0, g: 120, b: 90}],
+                check:        Rgb {r: 204, g: 121, b: 167}
+            }),
+            _ => None
+        };
+
+        Some(Theme {
+            name,
+            palette,
+            ascii
+        })
+    }
+
+    pub fn from_env(ascii: bool) -> Theme {
+        // The classic theme, unless NO_COLOR is set to anything (see no-color.org)
+        let no_colour: bool = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Theme::named(if no_colour {"none"} else {"classic"}, ascii).expect("Built in theme")
+    }
+
+    pub fn piece_symbol(&self, piece: char, colour: char) -> char {
+
+        // Without colour the two sides need different symbols, so white's are drawn hollow
+        if self.ascii {
+            return if colour == 'w' {piece.to_ascii_uppercase()} else {piece};
+        }
+
+        match (piece, colour == 'w' && self.palette.is_none()) {
+            ('k', false) => '♚',
+            ('q', false) => '♛',
+            ('r', false) => '♜',
+            ('b', false) => '♝',
+            ('n', false) => '♞',
+            ('p', false) => '♟',
+            ('k', true) => '♔',
+            ('q', true) => '♕',
+            ('r', true) => '♖',
+            ('b', true) => '♗',
+            ('n', true) => '♘',
+            ('p', true) => '♙',
+            _ => unreachable!("Invalid piece type")
+        }
+    }
+}